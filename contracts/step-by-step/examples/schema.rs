@@ -3,7 +3,15 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use step_by_step::msg::{ConfigResponse, ExecuteMsg, InstantiateMsg, QueryMsg};
+use step_by_step::lender::{FlashFeeResponse, FlashLenderExecuteMsg, FlashLenderQueryMsg};
+use step_by_step::msg::{
+    AllowlistResponse, ArbitrageResponse, ArbitragesResponse, BalancesResponse, ConfigResponse,
+    DcaResponse, DcaRunsResponse, DcasResponse, ExecuteMsg, ExecutorsResponse, InstantiateMsg,
+    OrderResponse, OrdersResponse, PairsResponse, QueryMsg, ReferencePrice, RoutesResponse,
+    SimulateStrategyResponse, StrategiesResponse, StrategyResponse,
+};
+use step_by_step::msgs::best_venue::BestVenueSwapMsg;
+use step_by_step::msgs::liquidity::{ProvideLiquidityMsg, WithdrawLiquidityMsg};
 use step_by_step::msgs::liquidity_pool::LiquidityPoolSwapMsg;
 use step_by_step::operations::StrategyStepOperation;
 use step_by_step::state::State;
@@ -19,6 +27,28 @@ fn main() {
     export_schema(&schema_for!(QueryMsg), &out_dir);
     export_schema(&schema_for!(State), &out_dir);
     export_schema(&schema_for!(ConfigResponse), &out_dir);
+    export_schema(&schema_for!(StrategyResponse), &out_dir);
+    export_schema(&schema_for!(StrategiesResponse), &out_dir);
+    export_schema(&schema_for!(AllowlistResponse), &out_dir);
+    export_schema(&schema_for!(ExecutorsResponse), &out_dir);
+    export_schema(&schema_for!(BalancesResponse), &out_dir);
+    export_schema(&schema_for!(SimulateStrategyResponse), &out_dir);
+    export_schema(&schema_for!(ReferencePrice), &out_dir);
+    export_schema(&schema_for!(PairsResponse), &out_dir);
+    export_schema(&schema_for!(RoutesResponse), &out_dir);
+    export_schema(&schema_for!(OrderResponse), &out_dir);
+    export_schema(&schema_for!(OrdersResponse), &out_dir);
+    export_schema(&schema_for!(DcaResponse), &out_dir);
+    export_schema(&schema_for!(DcasResponse), &out_dir);
+    export_schema(&schema_for!(DcaRunsResponse), &out_dir);
+    export_schema(&schema_for!(ArbitrageResponse), &out_dir);
+    export_schema(&schema_for!(ArbitragesResponse), &out_dir);
     export_schema(&schema_for!(LiquidityPoolSwapMsg), &out_dir);
+    export_schema(&schema_for!(ProvideLiquidityMsg), &out_dir);
+    export_schema(&schema_for!(WithdrawLiquidityMsg), &out_dir);
+    export_schema(&schema_for!(BestVenueSwapMsg), &out_dir);
     export_schema(&schema_for!(StrategyStepOperation), &out_dir);
+    export_schema(&schema_for!(FlashLenderExecuteMsg), &out_dir);
+    export_schema(&schema_for!(FlashLenderQueryMsg), &out_dir);
+    export_schema(&schema_for!(FlashFeeResponse), &out_dir);
 }
@@ -0,0 +1,52 @@
+use cosmwasm_std::{Addr, Deps, StdError, StdResult};
+
+use crate::asset::AssetInfo;
+use crate::state::{ALLOWED_FACTORIES, ALLOWED_PAIRS, ALLOWED_TOKENS, STATE};
+
+fn is_permissionless(deps: Deps) -> StdResult<bool> {
+    Ok(STATE.load(deps.storage)?.permissionless)
+}
+
+pub fn assert_token_allowed(deps: Deps, asset_info: &AssetInfo) -> StdResult<()> {
+    if let AssetInfo::Token { contract_addr } = asset_info {
+        if is_permissionless(deps)? {
+            return Ok(());
+        }
+
+        let token_addr = deps.api.addr_validate(contract_addr)?;
+        if !ALLOWED_TOKENS.has(deps.storage, &token_addr) {
+            return Err(StdError::generic_err(format!(
+                "token not allowed: {}",
+                token_addr
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Factories are queried for their pairs, so they must be allowlisted before any query,
+/// the pairs they created are trusted along with them.
+pub fn assert_factory_allowed(deps: Deps, factory_addr: &str) -> StdResult<()> {
+    let factory_addr = deps.api.addr_validate(factory_addr)?;
+    if is_permissionless(deps)? || ALLOWED_FACTORIES.has(deps.storage, &factory_addr) {
+        return Ok(());
+    }
+
+    Err(StdError::generic_err(format!(
+        "factory not allowed: {}",
+        factory_addr
+    )))
+}
+
+/// Pairs given by their address, without a factory, must be allowlisted themselves
+pub fn assert_pair_contract_allowed(deps: Deps, pair_addr: &Addr) -> StdResult<()> {
+    if is_permissionless(deps)? || ALLOWED_PAIRS.has(deps.storage, pair_addr) {
+        return Ok(());
+    }
+
+    Err(StdError::generic_err(format!(
+        "pair not allowed: {}",
+        pair_addr
+    )))
+}
@@ -0,0 +1,217 @@
+use cosmwasm_std::{
+    to_binary, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Order, Response, StdError, StdResult,
+    Storage, Uint128, WasmMsg,
+};
+use cw_storage_plus::{Bound, U64Key};
+use terra_cosmwasm::TerraMsgWrapper;
+
+use crate::asset::{Asset, AssetInfo};
+use crate::contract::{assert_valid_steps, execute_strategy, query_inventory};
+use crate::msg::{ArbitrageResponse, ArbitragesResponse, ExecuteMsg, StrategyStep};
+use crate::querier::query_balance;
+use crate::state::{Arbitrage, ARBITRAGES, ARBITRAGE_COUNT, STATE};
+use crate::tax::TaxCache;
+
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+fn load_arbitrage(storage: &dyn Storage, id: u64) -> StdResult<Arbitrage> {
+    ARBITRAGES
+        .may_load(storage, U64Key::from(id))?
+        .ok_or_else(|| StdError::generic_err(format!("arbitrage not found: {}", id)))
+}
+
+pub fn register_arbitrage(
+    deps: DepsMut,
+    info: MessageInfo,
+    steps: Vec<StrategyStep>,
+    minimum_profit: Uint128,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    assert_valid_steps(deps.as_ref(), &steps)?;
+    let offer_asset_info = steps.first().unwrap().get_from_asset();
+    if !offer_asset_info.equal(&steps.last().unwrap().get_to_asset()) {
+        return Err(StdError::generic_err(
+            "invalid arbitrage; the route must start and end on the same asset",
+        ));
+    }
+    if minimum_profit.is_zero() {
+        return Err(StdError::generic_err(
+            "invalid arbitrage; minimum profit must be positive",
+        ));
+    }
+
+    let id = ARBITRAGE_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    ARBITRAGE_COUNT.save(deps.storage, &id)?;
+    ARBITRAGES.save(
+        deps.storage,
+        U64Key::from(id),
+        &Arbitrage {
+            id,
+            steps,
+            minimum_profit,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("action", "register_arbitrage")
+        .add_attribute("arbitrage_id", id.to_string())
+        .add_attribute("asset", offer_asset_info.to_string())
+        .add_attribute("minimum_profit", minimum_profit))
+}
+
+pub fn delete_arbitrage(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: u64,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    load_arbitrage(deps.storage, id)?;
+    ARBITRAGES.remove(deps.storage, U64Key::from(id));
+
+    Ok(Response::new()
+        .add_attribute("action", "delete_arbitrage")
+        .add_attribute("arbitrage_id", id.to_string()))
+}
+
+/// Runs the loop on behalf of the owner, the output stays in the router
+/// and `SettleArbitrage` asserts the profit before paying the caller
+pub fn trigger_arbitrage(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+    amount: Uint128,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let arbitrage = load_arbitrage(deps.storage, id)?;
+    let state = STATE.load(deps.storage)?;
+
+    let asset_info = arbitrage.steps.first().unwrap().get_from_asset();
+    let inventory = query_inventory(deps.as_ref(), &env, &asset_info)?;
+    if amount.is_zero() || amount > inventory {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; amount: {}, inventory: {}",
+            amount, inventory
+        )));
+    }
+
+    let initial_balance = query_balance(
+        &deps.querier,
+        env.contract.address.clone(),
+        asset_info.clone(),
+    )?;
+    let settle = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_binary(&ExecuteMsg::SettleArbitrage {
+            id,
+            keeper: info.sender.to_string(),
+            asset_info: asset_info.clone(),
+            initial_balance,
+            minimum_profit: arbitrage.minimum_profit,
+        })?,
+    });
+
+    let receiver = env.contract.address.clone();
+    Ok(execute_strategy(
+        deps,
+        env,
+        state.owner,
+        receiver,
+        Asset {
+            info: asset_info,
+            amount,
+        },
+        arbitrage.steps,
+        None,
+        Uint128::zero(),
+    )?
+    .add_message(settle)
+    .add_attribute("arbitrage_id", id.to_string())
+    .add_attribute("keeper", info.sender))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn settle_arbitrage(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+    keeper: String,
+    asset_info: AssetInfo,
+    initial_balance: Uint128,
+    minimum_profit: Uint128,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    if env.contract.address != info.sender {
+        return Err(StdError::generic_err(format!(
+            "unauthorized settle; expected caller: {}, caller: {}",
+            env.contract.address, info.sender
+        )));
+    }
+
+    let balance = query_balance(&deps.querier, env.contract.address, asset_info.clone())?;
+    let profit = balance.checked_sub(initial_balance)?;
+    let bounty = profit * STATE.load(deps.storage)?.arbitrage_bounty;
+    let net_profit = profit.checked_sub(bounty)?;
+    if net_profit < minimum_profit {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; arbitrage {} profit: {}, minimum profit: {}",
+            id, net_profit, minimum_profit
+        )));
+    }
+
+    let mut response = Response::new()
+        .add_attribute("action", "settle_arbitrage")
+        .add_attribute("arbitrage_id", id.to_string())
+        .add_attribute("profit", net_profit)
+        .add_attribute("bounty", bounty);
+    let mut tax = TaxCache::default();
+    if !bounty.is_zero() {
+        response = response.add_message(
+            Asset {
+                info: asset_info,
+                amount: bounty,
+            }
+            .into_msg(&deps.querier, &mut tax, deps.api.addr_validate(&keeper)?)?,
+        );
+    }
+
+    Ok(response.add_attribute("tax_paid", tax.paid_to_string()))
+}
+
+fn arbitrage_to_response(arbitrage: Arbitrage) -> ArbitrageResponse {
+    ArbitrageResponse {
+        id: arbitrage.id,
+        steps: arbitrage.steps,
+        minimum_profit: arbitrage.minimum_profit,
+    }
+}
+
+pub fn query_arbitrage(deps: Deps, id: u64) -> StdResult<ArbitrageResponse> {
+    Ok(arbitrage_to_response(load_arbitrage(deps.storage, id)?))
+}
+
+pub fn query_arbitrages(
+    deps: Deps,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<ArbitragesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|id| Bound::exclusive(U64Key::from(id)));
+
+    let arbitrages = ARBITRAGES
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, arbitrage)| arbitrage_to_response(arbitrage)))
+        .collect::<StdResult<Vec<ArbitrageResponse>>>()?;
+
+    Ok(ArbitragesResponse { arbitrages })
+}
@@ -3,12 +3,13 @@ use serde::{Deserialize, Serialize};
 use std::fmt;
 
 use crate::querier::{query_native_balance, query_token_balance};
+use crate::tax::TaxCache;
 use cosmwasm_std::{
-    to_binary, Addr, Api, BankMsg, CanonicalAddr, Coin, CosmosMsg, Decimal, MessageInfo,
-    QuerierWrapper, StdError, StdResult, SubMsg, Uint128, WasmMsg,
+    to_binary, Addr, Api, BankMsg, CanonicalAddr, Coin, CosmosMsg, MessageInfo, QuerierWrapper,
+    StdError, StdResult, SubMsg, Uint128, WasmMsg,
 };
 use cw20::Cw20ExecuteMsg;
-use terra_cosmwasm::TerraQuerier;
+use std::fmt::Debug;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Asset {
@@ -22,48 +23,21 @@ impl fmt::Display for Asset {
     }
 }
 
-static DECIMAL_FRACTION: Uint128 = Uint128::new(1_000_000_000_000_000_000u128);
-
 impl Asset {
     pub fn is_native_token(&self) -> bool {
         self.info.is_native_token()
     }
 
-    pub fn compute_tax(&self, querier: &QuerierWrapper) -> StdResult<Uint128> {
-        let amount = self.amount;
-        if let AssetInfo::NativeToken { denom } = &self.info {
-            if denom == "uluna" {
-                Ok(Uint128::zero())
-            } else {
-                let terra_querier = TerraQuerier::new(querier);
-                let tax_rate: Decimal = (terra_querier.query_tax_rate()?).rate;
-                let tax_cap: Uint128 = (terra_querier.query_tax_cap(denom.to_string())?).cap;
-                Ok(std::cmp::min(
-                    amount.checked_sub(amount.multiply_ratio(
-                        DECIMAL_FRACTION,
-                        DECIMAL_FRACTION * tax_rate + DECIMAL_FRACTION,
-                    ))?,
-                    tax_cap,
-                ))
-            }
-        } else {
-            Ok(Uint128::zero())
-        }
-    }
-
-    pub fn deduct_tax(&self, querier: &QuerierWrapper) -> StdResult<Coin> {
-        let amount = self.amount;
-        if let AssetInfo::NativeToken { denom } = &self.info {
-            Ok(Coin {
-                denom: denom.to_string(),
-                amount: amount.checked_sub(self.compute_tax(querier)?)?,
-            })
-        } else {
-            Err(StdError::generic_err("cannot deduct tax from token asset"))
-        }
-    }
-
-    pub fn into_msg(self, querier: &QuerierWrapper, recipient: Addr) -> StdResult<CosmosMsg> {
+    /// Native transfers deduct their tax through `tax`, which records it as paid
+    pub fn into_msg<T>(
+        self,
+        querier: &QuerierWrapper,
+        tax: &mut TaxCache,
+        recipient: Addr,
+    ) -> StdResult<CosmosMsg<T>>
+    where
+        T: Clone + Debug + PartialEq + JsonSchema,
+    {
         let amount = self.amount;
 
         match &self.info {
@@ -75,15 +49,26 @@ impl Asset {
                 })?,
                 funds: vec![],
             })),
-            AssetInfo::NativeToken { .. } => Ok(CosmosMsg::Bank(BankMsg::Send {
+            AssetInfo::NativeToken { denom } => Ok(CosmosMsg::Bank(BankMsg::Send {
                 to_address: recipient.to_string(),
-                amount: vec![self.deduct_tax(querier)?],
+                amount: vec![Coin {
+                    denom: denom.to_string(),
+                    amount: tax.deduct_tax(querier, &self)?,
+                }],
             })),
         }
     }
 
-    pub fn into_submsg(self, querier: &QuerierWrapper, recipient: Addr) -> StdResult<SubMsg> {
-        Ok(SubMsg::new(self.into_msg(querier, recipient)?))
+    pub fn into_submsg<T>(
+        self,
+        querier: &QuerierWrapper,
+        tax: &mut TaxCache,
+        recipient: Addr,
+    ) -> StdResult<SubMsg<T>>
+    where
+        T: Clone + Debug + PartialEq + JsonSchema,
+    {
+        Ok(SubMsg::new(self.into_msg(querier, tax, recipient)?))
     }
 
     pub fn assert_sent_native_token_balance(&self, message_info: &MessageInfo) -> StdResult<()> {
@@ -111,14 +96,7 @@ impl Asset {
 
     pub fn to_raw(&self, api: &dyn Api) -> StdResult<AssetRaw> {
         Ok(AssetRaw {
-            info: match &self.info {
-                AssetInfo::NativeToken { denom } => AssetInfoRaw::NativeToken {
-                    denom: denom.to_string(),
-                },
-                AssetInfo::Token { contract_addr } => AssetInfoRaw::Token {
-                    contract_addr: api.addr_canonicalize(contract_addr.as_str())?,
-                },
-            },
+            info: self.info.to_raw(api)?,
             amount: self.amount,
         })
     }
@@ -148,8 +126,10 @@ impl AssetInfo {
             AssetInfo::NativeToken { denom } => Ok(AssetInfoRaw::NativeToken {
                 denom: denom.to_string(),
             }),
+            // non normalized addresses would not convert back to the same asset
             AssetInfo::Token { contract_addr } => Ok(AssetInfoRaw::Token {
-                contract_addr: api.addr_canonicalize(contract_addr.as_str())?,
+                contract_addr: api
+                    .addr_canonicalize(api.addr_validate(contract_addr.as_str())?.as_str())?,
             }),
         }
     }
@@ -197,6 +177,7 @@ impl AssetInfo {
         }
     }
 
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         match self {
             AssetInfo::Token { contract_addr, .. } => {
@@ -277,3 +258,55 @@ impl AssetInfoRaw {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockApi;
+    use proptest::prelude::*;
+
+    // small alphabets so that equal assets are generated often
+    fn asset_info() -> impl Strategy<Value = AssetInfo> {
+        prop_oneof![
+            "[a-c]{1,3}".prop_map(|denom| AssetInfo::NativeToken { denom }),
+            "[a-cA-C]{1,4}".prop_map(|contract_addr| AssetInfo::Token { contract_addr }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn raw_round_trip(info in asset_info(), amount in any::<u128>()) {
+            let api = MockApi::default();
+            let asset = Asset {
+                info: info.clone(),
+                amount: Uint128::new(amount),
+            };
+
+            match asset.to_raw(&api) {
+                Ok(raw) => {
+                    prop_assert_eq!(&asset, &raw.to_normal(&api).unwrap());
+                    prop_assert_eq!(&info, &raw.info.to_normal(&api).unwrap());
+                }
+                // only addresses rejected by the api can not be converted
+                Err(_) => match &info {
+                    AssetInfo::Token { contract_addr } => {
+                        prop_assert!(api.addr_validate(contract_addr).is_err())
+                    }
+                    AssetInfo::NativeToken { .. } => prop_assert!(false),
+                },
+            }
+        }
+
+        #[test]
+        fn equal_is_symmetric(a in asset_info(), b in asset_info()) {
+            let api = MockApi::default();
+            prop_assert_eq!(a.equal(&b), b.equal(&a));
+            prop_assert_eq!(a.equal(&b), a == b);
+
+            if let (Ok(raw_a), Ok(raw_b)) = (a.to_raw(&api), b.to_raw(&api)) {
+                prop_assert_eq!(raw_a.equal(&raw_b), raw_b.equal(&raw_a));
+                prop_assert_eq!(raw_a.equal(&raw_b), a.equal(&b));
+            }
+        }
+    }
+}
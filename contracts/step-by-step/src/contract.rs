@@ -1,23 +1,60 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    from_binary, to_binary, Addr, Binary, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Response,
-    StdError, StdResult, Uint128, WasmMsg,
+    from_binary, to_binary, Addr, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, Empty, Env,
+    MessageInfo, Order, Response, StdError, StdResult, Uint128, WasmMsg,
 };
 use cw2::set_contract_version;
 use cw20::Cw20ReceiveMsg;
+use cw_storage_plus::{Bound, U64Key};
 use terra_cosmwasm::TerraMsgWrapper;
 
+use crate::allowlist::assert_token_allowed;
+use crate::arbitrage::{
+    delete_arbitrage, query_arbitrage, query_arbitrages, register_arbitrage, settle_arbitrage,
+    trigger_arbitrage,
+};
 use crate::asset::{Asset, AssetInfo};
+use crate::dca::{
+    cancel_dca, create_dca, execute_dca, query_dca, query_dca_runs, query_dcas, record_dca_run,
+};
 use crate::error::ContractError;
-use crate::msg::{ConfigResponse, Cw20HookMsg, ExecuteMsg, InstantiateMsg, QueryMsg, StrategyStep};
-use crate::querier::query_balance;
-use crate::state::{State, STATE};
+use crate::escrow::escrowed;
+use crate::flash_loan::{
+    assert_flash_loan_repaid, execute_flash_loan_strategy, finalize_flash_loan, flash_loan,
+    flash_loan_callback, repay_flash_loan,
+};
+use crate::msg::{
+    AllowlistKind, AllowlistResponse, BalancesResponse, ConfigResponse, Cw20HookMsg, ExecuteMsg,
+    ExecutorAccess, ExecutorsResponse, InstantiateMsg, PairEntry, PairResponse, PairsResponse,
+    QueryMsg, ReferencePrice, SimulateStrategyResponse, StrategiesResponse, StrategyResponse,
+    StrategyStep,
+};
+use crate::msgs::liquidity_pool::query_pair_info;
+use crate::operations::{StepOperation, OPERATION_KINDS};
+use crate::oracle::assert_oracle_rate;
+use crate::orders::{
+    cancel_order, execute_order, place_order, query_order, query_orders, settle_order,
+    trigger_order,
+};
+use crate::querier::{query_all_balances, query_balance};
+use crate::routes::find_routes;
+use crate::state::{
+    allowlist, Execution, RegisteredPair, SavedStrategy, State, EXECUTION, EXECUTION_COUNT,
+    EXECUTORS, PAIRS, REFERENCE_PRICES, STATE, STRATEGIES, STRATEGY_COUNT,
+};
+use crate::tax::TaxCache;
 
 // version info for migration info
 const CONTRACT_NAME: &str = "ThyBotIsThick.StepByStep";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+// settings for pagination
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_STRATEGY_NAME_LENGTH: usize = 64;
+const MAX_CANDIDATES: usize = 5;
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
@@ -34,10 +71,23 @@ pub fn instantiate(
     let state = State {
         owner: info.sender.clone(),
         comission: msg.comission,
+        guardian: None,
+        paused: false,
+        paused_operations: vec![],
+        permissionless: false,
+        executor_access: ExecutorAccess::InventoryRestricted,
+        default_max_spread: None,
+        market_denoms: vec![],
+        order_bounty: Decimal::zero(),
+        dca_bounty: Decimal::zero(),
+        arbitrage_bounty: Decimal::zero(),
+        flash_lender: None,
+        flash_loan_fee: Decimal::zero(),
     };
 
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     STATE.save(deps.storage, &state)?;
+    STRATEGY_COUNT.save(deps.storage, &0)?;
 
     Ok(Response::new()
         .add_attribute("method", "instantiate")
@@ -56,22 +106,265 @@ pub fn execute(
         ExecuteMsg::ExecuteStrategy {
             steps,
             minimum_receive,
-        } => execute_strategy(deps, _env, info, steps, minimum_receive),
-        ExecuteMsg::ExecuteStrategyStep { step, to } => execute_step(deps, _env, info, step, to),
+        } => {
+            let offer = sent_native_offer(&steps, &info)?;
+            execute_strategy(
+                deps,
+                _env,
+                info.sender.clone(),
+                info.sender,
+                offer,
+                steps,
+                None,
+                minimum_receive,
+            )
+        }
+        ExecuteMsg::ExecuteBestStrategy {
+            candidates,
+            minimum_receive,
+        } => {
+            let offer = sent_native_offer(candidates.first().map_or(&[], Vec::as_slice), &info)?;
+            execute_best_strategy(
+                deps,
+                _env,
+                info.sender.clone(),
+                info.sender,
+                offer,
+                candidates,
+                minimum_receive,
+            )
+        }
+        ExecuteMsg::RegisterStrategy {
+            name,
+            steps,
+            global,
+            max_spread,
+        } => register_strategy(deps, info, name, steps, global.unwrap_or(false), max_spread),
+        ExecuteMsg::ExecuteStrategyById {
+            id,
+            amount,
+            minimum_receive,
+        } => execute_strategy_by_id(deps, _env, info, id, amount, minimum_receive),
+        ExecuteMsg::DeleteStrategy { id } => delete_strategy(deps, info, id),
+        ExecuteMsg::Deposit {} => deposit(deps, info.sender, info.funds),
+        ExecuteMsg::Withdraw { asset, recipient } => withdraw(deps, _env, info, asset, recipient),
+        ExecuteMsg::Sweep { assets, recipient } => sweep(deps, _env, info, assets, recipient),
+        ExecuteMsg::ExecuteInventoryStrategy {
+            steps,
+            amount,
+            minimum_profit,
+        } => execute_inventory_strategy(deps, _env, info, steps, amount, minimum_profit),
+        ExecuteMsg::ExecuteFlashLoanStrategy {
+            steps,
+            amount,
+            minimum_profit,
+        } => execute_flash_loan_strategy(deps, _env, info, steps, amount, minimum_profit),
+        ExecuteMsg::FlashLoanCallback {} => flash_loan_callback(deps, _env, info),
+        ExecuteMsg::RepayFlashLoan {} => repay_flash_loan(deps, _env, info),
+        ExecuteMsg::AssertFlashLoanRepaid {} => assert_flash_loan_repaid(deps, _env, info),
+        ExecuteMsg::FlashLoan {
+            asset,
+            amount,
+            callback,
+        } => flash_loan(deps, _env, info, asset, amount, callback),
+        ExecuteMsg::FinalizeFlashLoan {
+            borrower,
+            asset_info,
+            initial_inventory,
+            fee,
+        } => finalize_flash_loan(
+            deps,
+            _env,
+            info,
+            borrower,
+            asset_info,
+            initial_inventory,
+            fee,
+        ),
+        ExecuteMsg::UpdateConfig {
+            owner,
+            permissionless,
+            executor_access,
+            market_denoms,
+            order_bounty,
+            dca_bounty,
+            arbitrage_bounty,
+            flash_loan_fee,
+        } => update_config(
+            deps,
+            info,
+            owner,
+            permissionless,
+            executor_access,
+            market_denoms,
+            order_bounty,
+            dca_bounty,
+            arbitrage_bounty,
+            flash_loan_fee,
+        ),
+        ExecuteMsg::UpdateAllowlist { kind, add, remove } => {
+            update_allowlist(deps, info, kind, add, remove)
+        }
+        ExecuteMsg::UpdateDefaultMaxSpread { max_spread } => {
+            update_default_max_spread(deps, info, max_spread)
+        }
+        ExecuteMsg::UpdateFlashLender { flash_lender } => {
+            update_flash_lender(deps, info, flash_lender)
+        }
+        ExecuteMsg::UpdateExecutors { add, remove } => update_executors(deps, info, add, remove),
+        ExecuteMsg::UpdateGuardian { guardian } => update_guardian(deps, info, guardian),
+        ExecuteMsg::Pause { operations } => pause(deps, info, operations),
+        ExecuteMsg::Unpause { operations } => unpause(deps, info, operations),
+        ExecuteMsg::UpdateReferencePrice { token, price } => {
+            update_reference_price(deps, info, token, price)
+        }
+        ExecuteMsg::UpdatePairs { add, remove } => update_pairs(deps, info, add, remove),
+        ExecuteMsg::ExecuteStrategyStep {
+            execution_id,
+            step_index,
+            step,
+            to,
+        } => execute_step(deps, _env, info, execution_id, step_index, step, to),
         ExecuteMsg::FinalizeStrategy {
+            execution_id,
             receiver,
             asset_info,
             initial_balance,
             minimum_receive,
         } => finalize_strategy(
-            deps.as_ref(),
+            deps,
             _env,
             info,
-            deps.api.addr_validate(receiver.as_str())?,
+            execution_id,
+            receiver,
             asset_info,
             initial_balance,
             minimum_receive,
         ),
+        ExecuteMsg::PlaceOrder {
+            steps,
+            offer,
+            min_output,
+            expiry,
+        } => {
+            if !offer.is_native_token() {
+                return Err(StdError::generic_err(
+                    "assertion failed; cw20 offers must be sent through the cw20 receive hook",
+                ));
+            }
+            offer.assert_sent_native_token_balance(&info)?;
+            place_order(
+                deps,
+                _env,
+                info.sender,
+                steps,
+                offer,
+                min_output,
+                expiry,
+                None,
+            )
+        }
+        ExecuteMsg::PlaceConditionalOrder {
+            steps,
+            offer,
+            trigger,
+            min_output,
+            expiry,
+        } => {
+            if !offer.is_native_token() {
+                return Err(StdError::generic_err(
+                    "assertion failed; cw20 offers must be sent through the cw20 receive hook",
+                ));
+            }
+            offer.assert_sent_native_token_balance(&info)?;
+            place_order(
+                deps,
+                _env,
+                info.sender,
+                steps,
+                offer,
+                min_output,
+                expiry,
+                Some(trigger),
+            )
+        }
+        ExecuteMsg::CreateDca {
+            steps,
+            budget,
+            amount_per_run,
+            interval,
+            tolerance,
+        } => {
+            if !budget.is_native_token() {
+                return Err(StdError::generic_err(
+                    "assertion failed; cw20 offers must be sent through the cw20 receive hook",
+                ));
+            }
+            budget.assert_sent_native_token_balance(&info)?;
+            create_dca(
+                deps,
+                _env,
+                info.sender,
+                steps,
+                budget,
+                amount_per_run,
+                interval,
+                tolerance,
+            )
+        }
+        ExecuteMsg::ExecuteDca { id } => execute_dca(deps, _env, info, id),
+        ExecuteMsg::CancelDca { id } => cancel_dca(deps, info, id),
+        ExecuteMsg::RecordDcaRun {
+            id,
+            run,
+            keeper,
+            initial_balance,
+        } => record_dca_run(deps, _env, info, id, run, keeper, initial_balance),
+        ExecuteMsg::ExecuteOrder { id } => execute_order(deps, _env, info, id),
+        ExecuteMsg::TriggerOrder { id } => trigger_order(deps, _env, info, id),
+        ExecuteMsg::CancelOrder { id } => cancel_order(deps, _env, info, id),
+        ExecuteMsg::SettleOrder {
+            id,
+            owner,
+            keeper,
+            asset_info,
+            initial_balance,
+            min_output,
+        } => settle_order(
+            deps,
+            _env,
+            info,
+            id,
+            owner,
+            keeper,
+            asset_info,
+            initial_balance,
+            min_output,
+        ),
+        ExecuteMsg::RegisterArbitrage {
+            steps,
+            minimum_profit,
+        } => register_arbitrage(deps, info, steps, minimum_profit),
+        ExecuteMsg::DeleteArbitrage { id } => delete_arbitrage(deps, info, id),
+        ExecuteMsg::TriggerArbitrage { id, amount } => {
+            trigger_arbitrage(deps, _env, info, id, amount)
+        }
+        ExecuteMsg::SettleArbitrage {
+            id,
+            keeper,
+            asset_info,
+            initial_balance,
+            minimum_profit,
+        } => settle_arbitrage(
+            deps,
+            _env,
+            info,
+            id,
+            keeper,
+            asset_info,
+            initial_balance,
+            minimum_profit,
+        ),
     }
 }
 
@@ -81,30 +374,1001 @@ pub fn receive_cw20(
     info: MessageInfo,
     cw20_msg: Cw20ReceiveMsg,
 ) -> StdResult<Response<TerraMsgWrapper>> {
+    assert_not_paused(&STATE.load(deps.storage)?)?;
+
+    let sender = deps.api.addr_validate(&cw20_msg.sender)?;
+    let offer = Asset {
+        info: AssetInfo::Token {
+            contract_addr: info.sender.to_string(),
+        },
+        amount: cw20_msg.amount,
+    };
+
     match from_binary(&cw20_msg.msg)? {
         Cw20HookMsg::ExecuteStrategy {
             steps,
             minimum_receive,
-        } => execute_strategy(deps, _env, info, steps, minimum_receive),
+        } => execute_strategy(
+            deps,
+            _env,
+            sender.clone(),
+            sender,
+            offer,
+            steps,
+            None,
+            minimum_receive,
+        ),
+        Cw20HookMsg::ExecuteBestStrategy {
+            candidates,
+            minimum_receive,
+        } => execute_best_strategy(
+            deps,
+            _env,
+            sender.clone(),
+            sender,
+            offer,
+            candidates,
+            minimum_receive,
+        ),
+        Cw20HookMsg::ExecuteStrategyById {
+            id,
+            minimum_receive,
+        } => {
+            let strategy = load_executable_strategy(deps.as_ref(), id, &sender)?;
+            execute_strategy(
+                deps,
+                _env,
+                sender.clone(),
+                sender,
+                offer,
+                strategy.steps,
+                strategy.max_spread,
+                minimum_receive,
+            )
+        }
+        Cw20HookMsg::Deposit {} => deposit(deps, sender, vec![]).map(|response| {
+            response
+                .add_attribute("asset", offer.info.to_string())
+                .add_attribute("amount", offer.amount)
+        }),
+        Cw20HookMsg::PlaceOrder {
+            steps,
+            min_output,
+            expiry,
+        } => place_order(deps, _env, sender, steps, offer, min_output, expiry, None),
+        Cw20HookMsg::PlaceConditionalOrder {
+            steps,
+            trigger,
+            min_output,
+            expiry,
+        } => place_order(
+            deps,
+            _env,
+            sender,
+            steps,
+            offer,
+            min_output,
+            expiry,
+            Some(trigger),
+        ),
+        Cw20HookMsg::CreateDca {
+            steps,
+            amount_per_run,
+            interval,
+            tolerance,
+        } => create_dca(
+            deps,
+            _env,
+            sender,
+            steps,
+            offer,
+            amount_per_run,
+            interval,
+            tolerance,
+        ),
+    }
+}
+
+/// Native funds sent along with the strategy for its first step, strategies never fall back
+/// to the inventory which is only spent through `ExecuteInventoryStrategy`
+fn sent_native_offer(steps: &[StrategyStep], info: &MessageInfo) -> StdResult<Asset> {
+    let offer_asset_info = steps
+        .first()
+        .ok_or_else(|| StdError::generic_err("must provide steps"))?
+        .get_from_asset();
+    let denom = match &offer_asset_info {
+        AssetInfo::NativeToken { denom } => denom,
+        AssetInfo::Token { .. } => {
+            return Err(StdError::generic_err(
+                "assertion failed; cw20 offers must be sent through the cw20 receive hook",
+            ))
+        }
+    };
+
+    match info.funds.as_slice() {
+        [coin] if coin.denom == *denom && !coin.amount.is_zero() => Ok(Asset {
+            info: offer_asset_info,
+            amount: coin.amount,
+        }),
+        _ => Err(StdError::generic_err(format!(
+            "invalid funds; {} must be sent with this strategy",
+            offer_asset_info
+        ))),
+    }
+}
+
+pub(crate) fn is_executor(deps: Deps, state: &State, sender: &Addr) -> bool {
+    *sender == state.owner || EXECUTORS.has(deps.storage, sender)
+}
+
+/// Checks the sender can route its own funds, the inventory is only spent
+/// by the executors through `ExecuteInventoryStrategy` whatever the access mode
+pub(crate) fn assert_can_execute(deps: Deps, state: &State, sender: &Addr) -> StdResult<()> {
+    let allowed = match state.executor_access {
+        ExecutorAccess::Restricted => is_executor(deps, state, sender),
+        ExecutorAccess::InventoryRestricted => true,
+    };
+
+    if !allowed {
+        return Err(StdError::generic_err(format!(
+            "unauthorized; {} is not allowed to execute this strategy",
+            sender
+        )));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn assert_not_paused(state: &State) -> StdResult<()> {
+    if state.paused {
+        return Err(StdError::generic_err("router is paused"));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn assert_operations_not_paused(state: &State, steps: &[StrategyStep]) -> StdResult<()> {
+    for step in steps {
+        let kind = step.operation.describe();
+        if state.paused_operations.iter().any(|paused| paused == kind) {
+            return Err(StdError::generic_err(format!(
+                "operation {} is paused",
+                kind
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn assert_known_operations(operations: &[String]) -> StdResult<()> {
+    for operation in operations {
+        if !OPERATION_KINDS.contains(&operation.as_str()) {
+            return Err(StdError::generic_err(format!(
+                "unknown operation: {}",
+                operation
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn assert_max_spread(max_spread: Option<Decimal>) -> StdResult<()> {
+    match max_spread {
+        Some(max_spread) if max_spread > Decimal::one() => Err(StdError::generic_err(format!(
+            "invalid max spread: {} is greater than 1",
+            max_spread
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_config(
+    deps: DepsMut,
+    info: MessageInfo,
+    owner: Option<String>,
+    permissionless: Option<bool>,
+    executor_access: Option<ExecutorAccess>,
+    market_denoms: Option<Vec<String>>,
+    order_bounty: Option<Decimal>,
+    dca_bounty: Option<Decimal>,
+    arbitrage_bounty: Option<Decimal>,
+    flash_loan_fee: Option<Decimal>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let mut state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    if let Some(owner) = owner {
+        state.owner = deps.api.addr_validate(&owner)?;
+    }
+
+    if let Some(permissionless) = permissionless {
+        state.permissionless = permissionless;
+    }
+
+    if let Some(executor_access) = executor_access {
+        state.executor_access = executor_access;
+    }
+
+    if let Some(market_denoms) = market_denoms {
+        state.market_denoms = market_denoms;
+    }
+
+    if let Some(order_bounty) = order_bounty {
+        if order_bounty >= Decimal::one() {
+            return Err(StdError::generic_err(format!(
+                "invalid order bounty: {}, should be lower than 1",
+                order_bounty
+            )));
+        }
+        state.order_bounty = order_bounty;
+    }
+
+    if let Some(dca_bounty) = dca_bounty {
+        if dca_bounty >= Decimal::one() {
+            return Err(StdError::generic_err(format!(
+                "invalid dca bounty: {}, should be lower than 1",
+                dca_bounty
+            )));
+        }
+        state.dca_bounty = dca_bounty;
+    }
+
+    if let Some(arbitrage_bounty) = arbitrage_bounty {
+        if arbitrage_bounty >= Decimal::one() {
+            return Err(StdError::generic_err(format!(
+                "invalid arbitrage bounty: {}, should be lower than 1",
+                arbitrage_bounty
+            )));
+        }
+        state.arbitrage_bounty = arbitrage_bounty;
+    }
+
+    if let Some(flash_loan_fee) = flash_loan_fee {
+        if flash_loan_fee >= Decimal::one() {
+            return Err(StdError::generic_err(format!(
+                "invalid flash loan fee: {}, should be lower than 1",
+                flash_loan_fee
+            )));
+        }
+        state.flash_loan_fee = flash_loan_fee;
+    }
+
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new().add_attribute("action", "update_config"))
+}
+
+fn update_default_max_spread(
+    deps: DepsMut,
+    info: MessageInfo,
+    max_spread: Option<Decimal>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let mut state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    assert_max_spread(max_spread)?;
+    state.default_max_spread = max_spread;
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_default_max_spread")
+        .add_attribute(
+            "max_spread",
+            max_spread.map_or_else(String::new, |max_spread| max_spread.to_string()),
+        ))
+}
+
+fn update_flash_lender(
+    deps: DepsMut,
+    info: MessageInfo,
+    flash_lender: Option<String>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let mut state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    state.flash_lender = flash_lender
+        .map(|flash_lender| deps.api.addr_validate(&flash_lender))
+        .transpose()?;
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_flash_lender")
+        .add_attribute(
+            "flash_lender",
+            state
+                .flash_lender
+                .map_or_else(String::new, |flash_lender| flash_lender.to_string()),
+        ))
+}
+
+fn update_allowlist(
+    deps: DepsMut,
+    info: MessageInfo,
+    kind: AllowlistKind,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    let entries = allowlist(&kind);
+    for addr in add.iter() {
+        let addr = deps.api.addr_validate(addr)?;
+        entries.save(deps.storage, &addr, &Empty {})?;
+    }
+
+    for addr in remove.iter() {
+        let addr = deps.api.addr_validate(addr)?;
+        entries.remove(deps.storage, &addr);
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "update_allowlist")
+        .add_attribute("added", add.join(","))
+        .add_attribute("removed", remove.join(",")))
+}
+
+fn deposit(deps: DepsMut, sender: Addr, funds: Vec<Coin>) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    if sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    let mut response = Response::new().add_attribute("action", "deposit");
+    for coin in funds {
+        response = response
+            .add_attribute("asset", format!("NativeToken:{}", coin.denom))
+            .add_attribute("amount", coin.amount);
+    }
+
+    Ok(response)
+}
+
+/// Balance of `asset_info` the router can spend, escrowed funds excluded
+pub(crate) fn query_inventory(deps: Deps, env: &Env, asset_info: &AssetInfo) -> StdResult<Uint128> {
+    let balance = query_balance(
+        &deps.querier,
+        env.contract.address.clone(),
+        asset_info.clone(),
+    )?;
+    Ok(balance.saturating_sub(escrowed(deps, asset_info)?))
+}
+
+fn withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    asset: Asset,
+    recipient: Option<String>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
     }
+
+    let inventory = query_inventory(deps.as_ref(), &env, &asset.info)?;
+    if asset.amount > inventory {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; amount: {}, inventory: {}",
+            asset.amount, inventory
+        )));
+    }
+
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => info.sender,
+    };
+
+    let mut tax = TaxCache::default();
+    Ok(Response::new()
+        .add_attribute("action", "withdraw")
+        .add_attribute("asset", asset.info.to_string())
+        .add_attribute("amount", asset.amount)
+        .add_attribute("recipient", recipient.clone())
+        .add_message(asset.into_msg(&deps.querier, &mut tax, recipient)?)
+        .add_attribute("tax_paid", tax.paid_to_string()))
+}
+
+fn sweep(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    assets: Vec<AssetInfo>,
+    recipient: Option<String>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    let recipient = match recipient {
+        Some(recipient) => deps.api.addr_validate(&recipient)?,
+        None => info.sender,
+    };
+
+    let mut tax = TaxCache::default();
+    let mut response = Response::new()
+        .add_attribute("action", "sweep")
+        .add_attribute("recipient", recipient.clone());
+    for asset_info in assets {
+        let amount = query_inventory(deps.as_ref(), &env, &asset_info)?;
+        if amount.is_zero() {
+            continue;
+        }
+
+        let asset = Asset {
+            info: asset_info,
+            amount,
+        };
+        response = response
+            .add_attribute("swept", asset.to_string())
+            .add_message(asset.into_msg(&deps.querier, &mut tax, recipient.clone())?);
+    }
+
+    Ok(response.add_attribute("tax_paid", tax.paid_to_string()))
+}
+
+fn update_reference_price(
+    deps: DepsMut,
+    info: MessageInfo,
+    token: String,
+    price: Option<ReferencePrice>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    let token = deps.api.addr_validate(&token)?;
+    let response = Response::new()
+        .add_attribute("action", "update_reference_price")
+        .add_attribute("token", token.as_str());
+    match price {
+        Some(price) => {
+            if price.denom.is_empty() || price.price.is_zero() {
+                return Err(StdError::generic_err(
+                    "invalid reference price; denom and price must be set",
+                ));
+            }
+
+            REFERENCE_PRICES.save(deps.storage, &token, &price)?;
+            Ok(response
+                .add_attribute("denom", price.denom)
+                .add_attribute("price", price.price.to_string()))
+        }
+        None => {
+            REFERENCE_PRICES.remove(deps.storage, &token);
+            Ok(response)
+        }
+    }
+}
+
+fn update_pairs(
+    deps: DepsMut,
+    info: MessageInfo,
+    add: Vec<PairEntry>,
+    remove: Vec<String>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    for entry in add.iter() {
+        let factory_addr = deps.api.addr_validate(&entry.factory_addr)?;
+        let pair_info = query_pair_info(&deps.querier, factory_addr.clone(), &entry.asset_infos)?;
+        PAIRS.save(
+            deps.storage,
+            &deps.api.addr_validate(&pair_info.contract_addr)?,
+            &RegisteredPair {
+                factory_addr,
+                asset_infos: [
+                    entry.asset_infos[0].to_raw(deps.api)?,
+                    entry.asset_infos[1].to_raw(deps.api)?,
+                ],
+            },
+        )?;
+    }
+
+    for addr in remove.iter() {
+        PAIRS.remove(deps.storage, &deps.api.addr_validate(addr)?);
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "update_pairs")
+        .add_attribute("added", add.len().to_string())
+        .add_attribute("removed", remove.len().to_string()))
+}
+
+fn update_executors(
+    deps: DepsMut,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    for addr in add.iter() {
+        let addr = deps.api.addr_validate(addr)?;
+        EXECUTORS.save(deps.storage, &addr, &Empty {})?;
+    }
+
+    for addr in remove.iter() {
+        let addr = deps.api.addr_validate(addr)?;
+        EXECUTORS.remove(deps.storage, &addr);
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "update_executors")
+        .add_attribute("added", add.join(","))
+        .add_attribute("removed", remove.join(",")))
+}
+
+fn update_guardian(
+    deps: DepsMut,
+    info: MessageInfo,
+    guardian: Option<String>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let mut state = STATE.load(deps.storage)?;
+    if info.sender != state.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    state.guardian = guardian
+        .map(|guardian| deps.api.addr_validate(&guardian))
+        .transpose()?;
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_guardian")
+        .add_attribute(
+            "guardian",
+            state
+                .guardian
+                .map_or_else(String::new, |guardian| guardian.to_string()),
+        ))
+}
+
+fn pause(
+    deps: DepsMut,
+    info: MessageInfo,
+    operations: Option<Vec<String>>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let mut state = STATE.load(deps.storage)?;
+    if !state.can_pause(&info.sender) {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    match operations {
+        Some(operations) => {
+            assert_known_operations(&operations)?;
+            for operation in operations {
+                if !state.paused_operations.contains(&operation) {
+                    state.paused_operations.push(operation);
+                }
+            }
+        }
+        None => state.paused = true,
+    }
+
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "pause")
+        .add_attribute("paused", state.paused.to_string())
+        .add_attribute("paused_operations", state.paused_operations.join(",")))
+}
+
+fn unpause(
+    deps: DepsMut,
+    info: MessageInfo,
+    operations: Option<Vec<String>>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let mut state = STATE.load(deps.storage)?;
+    if !state.can_pause(&info.sender) {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    match operations {
+        Some(operations) => {
+            assert_known_operations(&operations)?;
+            state
+                .paused_operations
+                .retain(|paused| !operations.contains(paused));
+        }
+        None => {
+            state.paused = false;
+            state.paused_operations = vec![];
+        }
+    }
+
+    STATE.save(deps.storage, &state)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "unpause")
+        .add_attribute("paused", state.paused.to_string())
+        .add_attribute("paused_operations", state.paused_operations.join(",")))
+}
+
+pub(crate) fn assert_valid_steps(deps: Deps, steps: &[StrategyStep]) -> StdResult<()> {
+    if steps.is_empty() {
+        return Err(StdError::generic_err("must provide steps"));
+    }
+
+    for step in steps {
+        step.validate(deps)?;
+        assert_token_allowed(deps, &step.from_asset)?;
+        assert_token_allowed(deps, &step.to_asset)?;
+    }
+
+    for (previous, next) in steps.iter().zip(steps.iter().skip(1)) {
+        if !previous.to_asset.equal(&next.from_asset) {
+            return Err(StdError::generic_err(format!(
+                "invalid steps; step receives: {}, next step offers: {}",
+                previous.to_asset, next.from_asset
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn register_strategy(
+    deps: DepsMut,
+    info: MessageInfo,
+    name: String,
+    steps: Vec<StrategyStep>,
+    global: bool,
+    max_spread: Option<Decimal>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    if name.is_empty() || name.len() > MAX_STRATEGY_NAME_LENGTH {
+        return Err(StdError::generic_err(format!(
+            "strategy name should have between 1 and {} characters",
+            MAX_STRATEGY_NAME_LENGTH
+        )));
+    }
+
+    let owner = if global {
+        let state = STATE.load(deps.storage)?;
+        if info.sender != state.owner {
+            return Err(StdError::generic_err(
+                "unauthorized; only the owner can register global strategies",
+            ));
+        }
+
+        None
+    } else {
+        Some(info.sender.clone())
+    };
+
+    assert_valid_steps(deps.as_ref(), &steps)?;
+    assert_max_spread(max_spread)?;
+
+    let id = STRATEGY_COUNT.load(deps.storage)? + 1;
+    STRATEGY_COUNT.save(deps.storage, &id)?;
+    STRATEGIES.save(
+        deps.storage,
+        U64Key::from(id),
+        &SavedStrategy {
+            id,
+            name: name.clone(),
+            owner,
+            steps,
+            max_spread,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_attribute("action", "register_strategy")
+        .add_attribute("strategy_id", id.to_string())
+        .add_attribute("name", name)
+        .add_attribute("sender", info.sender))
+}
+
+fn load_executable_strategy(deps: Deps, id: u64, sender: &Addr) -> StdResult<SavedStrategy> {
+    let strategy = STRATEGIES.load(deps.storage, U64Key::from(id))?;
+
+    if let Some(owner) = &strategy.owner {
+        if owner != sender {
+            return Err(StdError::generic_err(format!(
+                "unauthorized; strategy {} can only be executed by {}",
+                id, owner
+            )));
+        }
+    }
+
+    Ok(strategy)
+}
+
+fn execute_strategy_by_id(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+    amount: Uint128,
+    minimum_receive: Uint128,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let strategy = load_executable_strategy(deps.as_ref(), id, &info.sender)?;
+
+    let offer_asset = Asset {
+        info: strategy.steps.first().unwrap().get_from_asset(),
+        amount,
+    };
+    if !offer_asset.is_native_token() {
+        return Err(StdError::generic_err(
+            "assertion failed; cw20 offers must be sent through the cw20 receive hook",
+        ));
+    }
+    offer_asset.assert_sent_native_token_balance(&info)?;
+
+    execute_strategy(
+        deps,
+        env,
+        info.sender.clone(),
+        info.sender,
+        offer_asset,
+        strategy.steps,
+        strategy.max_spread,
+        minimum_receive,
+    )
+}
+
+fn execute_inventory_strategy(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    steps: Vec<StrategyStep>,
+    amount: Uint128,
+    minimum_profit: Uint128,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    if !is_executor(deps.as_ref(), &state, &info.sender) {
+        return Err(StdError::generic_err(format!(
+            "unauthorized; {} is not an executor",
+            info.sender
+        )));
+    }
+
+    let (offer_asset_info, target_asset_info) = match (steps.first(), steps.last()) {
+        (Some(first), Some(last)) => (first.get_from_asset(), last.get_to_asset()),
+        _ => return Err(StdError::generic_err("must provide steps")),
+    };
+    if !offer_asset_info.equal(&target_asset_info) {
+        return Err(StdError::generic_err(
+            "assertion failed; inventory strategies must start and end on the same asset",
+        ));
+    }
+
+    let inventory = query_inventory(deps.as_ref(), &env, &offer_asset_info)?;
+    if amount.is_zero() || amount > inventory {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; amount: {}, inventory: {}",
+            amount, inventory
+        )));
+    }
+
+    // the output stays in the contract, finalize asserts the inventory grew by the minimum profit
+    let receiver = env.contract.address.clone();
+    execute_strategy(
+        deps,
+        env,
+        info.sender,
+        receiver,
+        Asset {
+            info: offer_asset_info,
+            amount,
+        },
+        steps,
+        None,
+        minimum_profit,
+    )
+}
+
+fn delete_strategy(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: u64,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let strategy = STRATEGIES.load(deps.storage, U64Key::from(id))?;
+    let state = STATE.load(deps.storage)?;
+
+    let is_strategy_owner = strategy.owner.as_ref() == Some(&info.sender);
+    if !is_strategy_owner && info.sender != state.owner {
+        return Err(StdError::generic_err(format!(
+            "unauthorized; cannot delete strategy {}",
+            id
+        )));
+    }
+
+    STRATEGIES.remove(deps.storage, U64Key::from(id));
+
+    Ok(Response::new()
+        .add_attribute("action", "delete_strategy")
+        .add_attribute("strategy_id", id.to_string()))
+}
+
+/// Executes the candidate with the highest simulated output for the offer, candidates that
+/// can not be simulated are skipped.
+fn execute_best_strategy(
+    deps: DepsMut,
+    env: Env,
+    sender: Addr,
+    receiver: Addr,
+    offer: Asset,
+    candidates: Vec<Vec<StrategyStep>>,
+    minimum_receive: Uint128,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    if candidates.is_empty() || candidates.len() > MAX_CANDIDATES {
+        return Err(StdError::generic_err(format!(
+            "invalid candidates; expected between 1 and {} candidates",
+            MAX_CANDIDATES
+        )));
+    }
+    for steps in candidates.iter() {
+        assert_valid_steps(deps.as_ref(), steps)?;
+    }
+
+    let from_asset_info = candidates[0].first().unwrap().get_from_asset();
+    let target_asset_info = candidates[0].last().unwrap().get_to_asset();
+    if candidates.iter().any(|steps| {
+        !steps
+            .first()
+            .unwrap()
+            .get_from_asset()
+            .equal(&from_asset_info)
+            || !steps
+                .last()
+                .unwrap()
+                .get_to_asset()
+                .equal(&target_asset_info)
+    }) {
+        return Err(StdError::generic_err(format!(
+            "invalid candidates; every candidate must go from {} to {}",
+            from_asset_info, target_asset_info
+        )));
+    }
+
+    let (index, return_amount) = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, steps)| {
+            let amounts = simulate_steps(deps.as_ref(), steps, offer.amount, true).ok()?;
+            Some((index, *amounts.last()?))
+        })
+        // the first candidate wins ties
+        .fold(
+            None,
+            |best: Option<(usize, Uint128)>, (index, amount)| match best {
+                Some((_, best_amount)) if best_amount >= amount => best,
+                _ => Some((index, amount)),
+            },
+        )
+        .ok_or_else(|| StdError::generic_err("no candidate could be simulated"))?;
+
+    let steps = candidates.into_iter().nth(index).unwrap();
+    Ok(execute_strategy(
+        deps,
+        env,
+        sender,
+        receiver,
+        offer,
+        steps,
+        None,
+        minimum_receive,
+    )?
+    .add_attribute("candidate", index.to_string())
+    .add_attribute("simulated_amount", return_amount))
 }
 
-fn execute_strategy(
+/// Starts a new execution and returns its id with the messages running each step,
+/// the last step sends its output to `last_step_to` when set
+pub(crate) fn step_messages(
     deps: DepsMut,
+    env: &Env,
+    offer_amount: Uint128,
+    steps: Vec<StrategyStep>,
+    max_spread: Option<Decimal>,
+    last_step_to: Option<String>,
+) -> StdResult<(u64, Vec<CosmosMsg<TerraMsgWrapper>>)> {
+    let execution_id = EXECUTION_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    EXECUTION_COUNT.save(deps.storage, &execution_id)?;
+    EXECUTION.save(
+        deps.storage,
+        &Execution {
+            id: execution_id,
+            offer_amount,
+            previous_ask_balance: Uint128::zero(),
+            tax: TaxCache::default(),
+            max_spread,
+        },
+    )?;
+
+    let steps_len = steps.len();
+    let mut step_index = 0;
+    let messages = steps
+        .into_iter()
+        .map(|op| {
+            step_index += 1;
+            Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: env.contract.address.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::ExecuteStrategyStep {
+                    execution_id,
+                    step_index,
+                    step: op,
+                    to: if step_index as usize == steps_len {
+                        last_step_to.clone()
+                    } else {
+                        None
+                    },
+                })?,
+            }))
+        })
+        .collect::<StdResult<Vec<CosmosMsg<TerraMsgWrapper>>>>()?;
+
+    Ok((execution_id, messages))
+}
+
+/// `max_spread` applies to the pool swaps that do not set one, falling back to the configured default
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn execute_strategy(
+    mut deps: DepsMut,
     env: Env,
-    info: MessageInfo,
+    sender: Addr,
+    receiver: Addr,
+    offer: Asset,
     steps: Vec<StrategyStep>,
+    max_spread: Option<Decimal>,
     minimum_receive: Uint128,
 ) -> StdResult<Response<TerraMsgWrapper>> {
-    let steps_len = steps.len();
-    if steps_len == 0 {
-        return Err(StdError::generic_err("must provide steps"));
+    let state = STATE.load(deps.storage)?;
+    assert_not_paused(&state)?;
+    assert_operations_not_paused(&state, &steps)?;
+    assert_valid_steps(deps.as_ref(), &steps)?;
+    assert_can_execute(deps.as_ref(), &state, &sender)?;
+
+    // only inventory strategies keep their output in the router
+    if receiver != env.contract.address {
+        if let Some(step) = steps.iter().find(|step| step.operation.uses_inventory()) {
+            return Err(StdError::generic_err(format!(
+                "invalid steps; {} uses the router inventory, it can only run in inventory strategies",
+                step.operation.describe()
+            )));
+        }
     }
 
-    let to = info.sender;
+    let steps_len = steps.len();
+    let to = receiver;
+    // when the contract receives the output the last step does not need to send it
+    let last_step_to = if to == env.contract.address {
+        None
+    } else {
+        Some(to.to_string())
+    };
     let from_asset_info = steps.first().unwrap().get_from_asset();
     let target_asset_info = steps.last().unwrap().get_to_asset();
 
+    if !offer.info.equal(&from_asset_info) {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; strategy offer asset: {}, sent asset: {}",
+            from_asset_info, offer.info
+        )));
+    }
+
     if from_asset_info.equal(&target_asset_info) {
         let current_amount =
             query_balance(&deps.querier, env.contract.address.clone(), from_asset_info)?;
@@ -117,46 +1381,48 @@ fn execute_strategy(
         }
     }
 
-    let mut step_index = 0;
-    let mut messages: Vec<CosmosMsg<TerraMsgWrapper>> = steps
-        .into_iter()
-        .map(|op| {
-            step_index += 1;
-            Ok(CosmosMsg::Wasm(WasmMsg::Execute {
-                contract_addr: env.contract.address.to_string(),
-                funds: vec![],
-                msg: to_binary(&ExecuteMsg::ExecuteStrategyStep {
-                    step: op,
-                    to: if step_index == steps_len {
-                        Some(to.to_string())
-                    } else {
-                        None
-                    },
-                })?,
-            }))
-        })
-        .collect::<StdResult<Vec<CosmosMsg<TerraMsgWrapper>>>>()?;
+    let (execution_id, mut messages) = step_messages(
+        deps.branch(),
+        &env,
+        offer.amount,
+        steps,
+        max_spread.or(state.default_max_spread),
+        last_step_to,
+    )?;
 
-    // Execute minimum amount assertion
-    let receiver_balance = target_asset_info.query_pool(&deps.querier, deps.api, to.clone())?;
+    // Execute minimum amount assertion, the router asserts its inventory grew
+    let receiver_balance = if to == env.contract.address {
+        query_inventory(deps.as_ref(), &env, &target_asset_info)?
+    } else {
+        target_asset_info.query_pool(&deps.querier, deps.api, to.clone())?
+    };
     messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
         contract_addr: env.contract.address.to_string(),
         funds: vec![],
         msg: to_binary(&ExecuteMsg::FinalizeStrategy {
+            execution_id,
             receiver: to.to_string(),
             asset_info: target_asset_info,
             initial_balance: receiver_balance,
-            minimum_receive: minimum_receive,
+            minimum_receive,
         })?,
     }));
 
-    Ok(Response::new().add_messages(messages))
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "execute_strategy")
+        .add_attribute("execution_id", execution_id.to_string())
+        .add_attribute("sender", sender)
+        .add_attribute("receiver", to)
+        .add_attribute("steps", steps_len.to_string()))
 }
 
 fn execute_step(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
+    execution_id: u64,
+    step_index: u32,
     step: StrategyStep,
     to: Option<String>,
 ) -> StdResult<Response<TerraMsgWrapper>> {
@@ -168,22 +1434,83 @@ fn execute_step(
     }
 
     let contract_addr = env.contract.address;
+    let mut execution = EXECUTION.load(deps.storage)?;
+    if execution.id != execution_id {
+        return Err(StdError::generic_err(format!(
+            "unexpected step; current execution: {}, step execution: {}",
+            execution.id, execution_id
+        )));
+    }
 
-    let amount = query_balance(&deps.querier, contract_addr, step.get_from_asset())?;
+    let balance = query_balance(&deps.querier, contract_addr.clone(), step.get_from_asset())?;
+    // strategies only spend their offer and what the previous steps received
+    let amount = if step_index == 1 {
+        execution.offer_amount
+    } else {
+        balance.checked_sub(execution.previous_ask_balance)?
+    };
     let from_asset = Asset {
         info: step.get_from_asset(),
-        amount: amount,
+        amount,
     };
     let to_asset_info = step.get_to_asset();
 
-    return step.operation.create_execution_message(deps.as_ref(), from_asset, to_asset_info, to);
+    if to.is_none() {
+        execution.previous_ask_balance =
+            query_balance(&deps.querier, contract_addr, to_asset_info.clone())?;
+    }
+
+    let oracle_rates = match step.max_oracle_deviation {
+        Some(max_deviation) => Some(assert_oracle_rate(
+            deps.as_ref(),
+            &mut execution.tax,
+            &step,
+            &from_asset,
+            to.is_some(),
+            max_deviation,
+        )?),
+        None => None,
+    };
+
+    let operation = step.operation.resolve(
+        deps.as_ref(),
+        &mut execution.tax,
+        &from_asset,
+        &to_asset_info,
+        execution.max_spread,
+    )?;
+    let mut response = operation.create_execution_message(
+        deps.as_ref(),
+        &mut execution.tax,
+        from_asset.clone(),
+        to_asset_info.clone(),
+        to,
+    )?;
+    EXECUTION.save(deps.storage, &execution)?;
+
+    if let Some((rate, oracle_rate)) = oracle_rates {
+        response = response
+            .add_attribute("rate", rate.to_string())
+            .add_attribute("oracle_rate", oracle_rate.to_string());
+    }
+
+    Ok(response
+        .add_attribute("action", "execute_step")
+        .add_attribute("execution_id", execution_id.to_string())
+        .add_attribute("step_index", step_index.to_string())
+        .add_attribute("operation", step.operation.describe())
+        .add_attribute("offer_asset", from_asset.info.to_string())
+        .add_attribute("offer_amount", from_asset.amount)
+        .add_attribute("ask_asset", to_asset_info.to_string()))
 }
 
+#[allow(clippy::too_many_arguments)]
 fn finalize_strategy(
-    deps: Deps,
+    deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    receiver: Addr,
+    execution_id: u64,
+    receiver: String,
     target_asset_info: AssetInfo,
     initial_balance: Uint128,
     minimum_receive: Uint128,
@@ -195,8 +1522,25 @@ fn finalize_strategy(
         )));
     }
 
-    let current_balance = query_balance(&deps.querier, receiver, target_asset_info.clone())?;
-    let swap_amount = current_balance.checked_sub(initial_balance)?;
+    let tax = EXECUTION
+        .may_load(deps.storage)?
+        .map(|execution| execution.tax)
+        .unwrap_or_default();
+    EXECUTION.remove(deps.storage);
+
+    let receiver = deps.api.addr_validate(&receiver)?;
+    // escrowed funds are not part of the router inventory
+    let current_balance = if receiver == env.contract.address {
+        query_inventory(deps.as_ref(), &env, &target_asset_info)?
+    } else {
+        query_balance(&deps.querier, receiver.clone(), target_asset_info.clone())?
+    };
+    let swap_amount = current_balance.checked_sub(initial_balance).map_err(|_| {
+        StdError::generic_err(format!(
+            "assertion failed; balance decreased from {} to {}",
+            initial_balance, current_balance
+        ))
+    })?;
 
     if swap_amount < minimum_receive {
         return Err(StdError::generic_err(format!(
@@ -206,32 +1550,315 @@ fn finalize_strategy(
     }
 
     Ok(Response::default()
+        .add_attribute("action", "finalize_strategy")
+        .add_attribute("execution_id", execution_id.to_string())
+        .add_attribute("receiver", receiver)
         .add_attribute("initial_balance", initial_balance)
         .add_attribute("final_balance", current_balance)
-        .add_attribute("target_asset", target_asset_info.to_string()))
+        .add_attribute("target_asset", target_asset_info.to_string())
+        .add_attribute("tax_paid", tax.paid_to_string()))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
+        QueryMsg::Strategy { id } => to_binary(&query_strategy(deps, id)?),
+        QueryMsg::Strategies {
+            owner,
+            start_after,
+            limit,
+        } => to_binary(&query_strategies(deps, owner, start_after, limit)?),
+        QueryMsg::Allowlist {
+            kind,
+            start_after,
+            limit,
+        } => to_binary(&query_allowlist(deps, kind, start_after, limit)?),
+        QueryMsg::Executors { start_after, limit } => {
+            to_binary(&query_executors(deps, start_after, limit)?)
+        }
+        QueryMsg::Balances { tokens } => to_binary(&query_balances(deps, env, tokens)?),
+        QueryMsg::ReferencePrice { token } => to_binary(&query_reference_price(deps, token)?),
+        QueryMsg::Pairs { start_after, limit } => {
+            to_binary(&query_pairs(deps, start_after, limit)?)
+        }
+        QueryMsg::FindRoutes {
+            offer_asset,
+            ask_asset,
+            max_hops,
+            amount,
+            limit,
+        } => to_binary(&find_routes(
+            deps,
+            offer_asset,
+            ask_asset,
+            max_hops,
+            amount,
+            limit,
+        )?),
+        QueryMsg::SimulateStrategy { steps, amount } => {
+            to_binary(&query_simulate_strategy(deps, steps, amount)?)
+        }
+        QueryMsg::Order { id } => to_binary(&query_order(deps, id)?),
+        QueryMsg::Orders {
+            owner,
+            start_after,
+            limit,
+        } => to_binary(&query_orders(deps, owner, start_after, limit)?),
+        QueryMsg::Dca { id } => to_binary(&query_dca(deps, id)?),
+        QueryMsg::Dcas {
+            owner,
+            start_after,
+            limit,
+        } => to_binary(&query_dcas(deps, owner, start_after, limit)?),
+        QueryMsg::DcaRuns {
+            id,
+            start_after,
+            limit,
+        } => to_binary(&query_dca_runs(deps, id, start_after, limit)?),
+        QueryMsg::Arbitrage { id } => to_binary(&query_arbitrage(deps, id)?),
+        QueryMsg::Arbitrages { start_after, limit } => {
+            to_binary(&query_arbitrages(deps, start_after, limit)?)
+        }
     }
 }
 
 pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
     let state = STATE.load(deps.storage)?;
     let resp = ConfigResponse {
+        owner: state.owner,
         comission: state.comission,
+        guardian: state.guardian,
+        paused: state.paused,
+        paused_operations: state.paused_operations,
+        permissionless: state.permissionless,
+        executor_access: state.executor_access,
+        default_max_spread: state.default_max_spread,
+        market_denoms: state.market_denoms,
+        order_bounty: state.order_bounty,
+        dca_bounty: state.dca_bounty,
+        arbitrage_bounty: state.arbitrage_bounty,
+        flash_lender: state.flash_lender,
+        flash_loan_fee: state.flash_loan_fee,
     };
 
     Ok(resp)
 }
 
+fn strategy_to_response(strategy: SavedStrategy) -> StrategyResponse {
+    StrategyResponse {
+        id: strategy.id,
+        name: strategy.name,
+        owner: strategy.owner,
+        steps: strategy.steps,
+        max_spread: strategy.max_spread,
+    }
+}
+
+pub fn query_strategy(deps: Deps, id: u64) -> StdResult<StrategyResponse> {
+    let strategy = STRATEGIES.load(deps.storage, U64Key::from(id))?;
+    Ok(strategy_to_response(strategy))
+}
+
+pub fn query_strategies(
+    deps: Deps,
+    owner: Option<String>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<StrategiesResponse> {
+    let owner = owner
+        .map(|owner| deps.api.addr_validate(&owner))
+        .transpose()?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|id| Bound::exclusive(U64Key::from(id)));
+
+    let strategies = STRATEGIES
+        .range(deps.storage, start, None, Order::Ascending)
+        .map(|item| item.map(|(_, strategy)| strategy))
+        .filter(|item| match (item, &owner) {
+            (Ok(strategy), Some(owner)) => strategy.owner.as_ref() == Some(owner),
+            _ => true,
+        })
+        .take(limit)
+        .map(|item| item.map(strategy_to_response))
+        .collect::<StdResult<Vec<StrategyResponse>>>()?;
+
+    Ok(StrategiesResponse { strategies })
+}
+
+pub fn query_allowlist(
+    deps: Deps,
+    kind: AllowlistKind,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<AllowlistResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?
+        .map(|addr| Bound::exclusive(addr.as_bytes()));
+
+    let addresses = allowlist(&kind)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|key| {
+            String::from_utf8(key)
+                .map(Addr::unchecked)
+                .map_err(StdError::invalid_utf8)
+        })
+        .collect::<StdResult<Vec<Addr>>>()?;
+
+    Ok(AllowlistResponse { kind, addresses })
+}
+
+pub fn query_executors(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ExecutorsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?
+        .map(|addr| Bound::exclusive(addr.as_bytes()));
+
+    let executors = EXECUTORS
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|key| {
+            String::from_utf8(key)
+                .map(Addr::unchecked)
+                .map_err(StdError::invalid_utf8)
+        })
+        .collect::<StdResult<Vec<Addr>>>()?;
+
+    Ok(ExecutorsResponse { executors })
+}
+
+pub fn query_reference_price(deps: Deps, token: String) -> StdResult<ReferencePrice> {
+    REFERENCE_PRICES.load(deps.storage, &deps.api.addr_validate(&token)?)
+}
+
+pub fn query_pairs(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<PairsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after
+        .map(|addr| deps.api.addr_validate(&addr))
+        .transpose()?
+        .map(|addr| Bound::exclusive(addr.as_bytes()));
+
+    let pairs = PAIRS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            let (key, pair) = item?;
+            Ok(PairResponse {
+                contract_addr: String::from_utf8(key)
+                    .map(Addr::unchecked)
+                    .map_err(StdError::invalid_utf8)?,
+                factory_addr: pair.factory_addr,
+                asset_infos: [
+                    pair.asset_infos[0].to_normal(deps.api)?,
+                    pair.asset_infos[1].to_normal(deps.api)?,
+                ],
+            })
+        })
+        .collect::<StdResult<Vec<PairResponse>>>()?;
+
+    Ok(PairsResponse { pairs })
+}
+
+pub fn query_balances(
+    deps: Deps,
+    env: Env,
+    tokens: Option<Vec<String>>,
+) -> StdResult<BalancesResponse> {
+    let mut balances: Vec<Asset> = query_all_balances(&deps.querier, &env.contract.address)?
+        .into_iter()
+        .map(|coin| Asset {
+            info: AssetInfo::NativeToken { denom: coin.denom },
+            amount: coin.amount,
+        })
+        .collect();
+
+    for token in tokens.unwrap_or_default() {
+        let info = AssetInfo::Token {
+            contract_addr: deps.api.addr_validate(&token)?.to_string(),
+        };
+        let amount = query_balance(&deps.querier, env.contract.address.clone(), info.clone())?;
+        balances.push(Asset { info, amount });
+    }
+
+    Ok(BalancesResponse { balances })
+}
+
+/// Runs `amount` through the steps' simulations, returns the amount received from each step,
+/// `sends_out` when the last step delivers out of the router
+pub fn simulate_steps(
+    deps: Deps,
+    steps: &[StrategyStep],
+    amount: Uint128,
+    sends_out: bool,
+) -> StdResult<Vec<Uint128>> {
+    let mut tax = TaxCache::default();
+    let mut amounts = vec![];
+    let mut amount = amount;
+    for (index, step) in steps.iter().enumerate() {
+        amount = step.operation.simulate(
+            deps,
+            &mut tax,
+            &Asset {
+                info: step.get_from_asset(),
+                amount,
+            },
+            &step.get_to_asset(),
+            sends_out && index + 1 == steps.len(),
+        )?;
+        amounts.push(amount);
+    }
+
+    Ok(amounts)
+}
+
+pub fn query_simulate_strategy(
+    deps: Deps,
+    steps: Vec<StrategyStep>,
+    amount: Uint128,
+) -> StdResult<SimulateStrategyResponse> {
+    assert_valid_steps(deps, &steps)?;
+
+    let amounts = simulate_steps(deps, &steps, amount, true)?;
+    Ok(SimulateStrategyResponse {
+        return_amount: amounts.last().copied().unwrap_or_default(),
+        amounts,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::msgs::market::MarketSwapMsg;
+    use crate::operations::StrategyStepOperation;
     use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{coins, from_binary};
+    use cosmwasm_std::{attr, coins, from_binary, from_slice, BankMsg, Decimal};
+
+    fn market_step(from_denom: &str, to_denom: &str) -> StrategyStep {
+        StrategyStep {
+            from_asset: AssetInfo::NativeToken {
+                denom: from_denom.to_string(),
+            },
+            to_asset: AssetInfo::NativeToken {
+                denom: to_denom.to_string(),
+            },
+            operation: StrategyStepOperation::MarketSwapOperation {
+                msg: MarketSwapMsg {},
+            },
+            max_oracle_deviation: None,
+        }
+    }
 
     #[test]
     fn proper_initialization() {
@@ -249,4 +1876,815 @@ mod tests {
         let value: ConfigResponse = from_binary(&res).unwrap();
         assert_eq!(6, value.comission);
     }
+
+    #[test]
+    fn register_and_query_strategies() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let steps = vec![market_step("uusd", "ukrw"), market_step("ukrw", "uluna")];
+
+        // only the owner registers global strategies
+        let msg = ExecuteMsg::RegisterStrategy {
+            name: "global".to_string(),
+            steps: steps.clone(),
+            global: Some(true),
+            max_spread: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::RegisterStrategy {
+            name: "mine".to_string(),
+            steps: steps.clone(),
+            global: None,
+            max_spread: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("bot", &[]), msg).unwrap();
+
+        // steps that do not chain are rejected
+        let msg = ExecuteMsg::RegisterStrategy {
+            name: "broken".to_string(),
+            steps: vec![market_step("uusd", "ukrw"), market_step("umnt", "uluna")],
+            global: None,
+            max_spread: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("bot", &[]), msg);
+        assert!(res.is_err());
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Strategy { id: 2 }).unwrap();
+        let strategy: StrategyResponse = from_binary(&res).unwrap();
+        assert_eq!("mine", strategy.name);
+        assert_eq!(Some(Addr::unchecked("bot")), strategy.owner);
+        assert_eq!(steps, strategy.steps);
+
+        let msg = QueryMsg::Strategies {
+            owner: None,
+            start_after: None,
+            limit: None,
+        };
+        let res: StrategiesResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(2, res.strategies.len());
+
+        let msg = QueryMsg::Strategies {
+            owner: Some("bot".to_string()),
+            start_after: None,
+            limit: None,
+        };
+        let res: StrategiesResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(1, res.strategies.len());
+        assert_eq!(2, res.strategies[0].id);
+    }
+
+    #[test]
+    fn execute_and_delete_strategy_by_id() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::RegisterStrategy {
+            name: "mine".to_string(),
+            steps: vec![market_step("uusd", "ukrw"), market_step("ukrw", "uluna")],
+            global: None,
+            max_spread: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("bot", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::ExecuteStrategyById {
+            id: 1,
+            amount: Uint128::new(100),
+            minimum_receive: Uint128::new(1),
+        };
+
+        // user-scoped strategies are private to their owner
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("other", &coins(100, "uusd")),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+
+        // the sent funds must match the amount
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &coins(10, "uusd")),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &coins(100, "uusd")),
+            msg,
+        )
+        .unwrap();
+        assert_eq!(3, res.messages.len());
+
+        let msg = ExecuteMsg::DeleteStrategy { id: 1 };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("other", &[]),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+        execute(deps.as_mut(), mock_env(), mock_info("bot", &[]), msg).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Strategy { id: 1 });
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn strategy_emits_hop_attributes() {
+        let mut deps = mock_dependencies(&coins(100, "uusd"));
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::ExecuteStrategy {
+            steps: vec![market_step("uusd", "ukrw"), market_step("ukrw", "uluna")],
+            minimum_receive: Uint128::new(1),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &coins(100, "uusd")),
+            msg,
+        )
+        .unwrap();
+        assert_eq!(3, res.messages.len());
+        assert_eq!(
+            vec![
+                attr("action", "execute_strategy"),
+                attr("execution_id", "1"),
+                attr("sender", "creator"),
+                attr("receiver", "creator"),
+                attr("steps", "2"),
+            ],
+            res.attributes
+        );
+
+        let env = mock_env();
+        let msg = ExecuteMsg::ExecuteStrategyStep {
+            execution_id: 1,
+            step_index: 1,
+            step: market_step("uusd", "ukrw"),
+            to: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(env.contract.address.as_str(), &[]),
+            msg,
+        )
+        .unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(
+            vec![
+                attr("pair_address", "market"),
+                attr("action", "execute_step"),
+                attr("execution_id", "1"),
+                attr("step_index", "1"),
+                attr("operation", "market_swap"),
+                attr("offer_asset", "NativeToken:uusd"),
+                attr("offer_amount", "100"),
+                attr("ask_asset", "NativeToken:ukrw"),
+            ],
+            res.attributes
+        );
+    }
+
+    #[test]
+    fn pause_blocks_strategies() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::UpdateGuardian {
+            guardian: Some("guardian".to_string()),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("guardian", &[]),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let strategy = ExecuteMsg::ExecuteStrategy {
+            steps: vec![market_step("uusd", "ukrw")],
+            minimum_receive: Uint128::new(1),
+        };
+
+        let msg = ExecuteMsg::Pause {
+            operations: Some(vec!["market_swap".to_string()]),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+        execute(deps.as_mut(), mock_env(), mock_info("guardian", &[]), msg).unwrap();
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &coins(100, "uusd")),
+            strategy.clone(),
+        );
+        assert!(res.is_err());
+
+        let msg = ExecuteMsg::Unpause {
+            operations: Some(vec!["market_swap".to_string()]),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("guardian", &[]), msg).unwrap();
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &coins(100, "uusd")),
+            strategy.clone(),
+        )
+        .unwrap();
+
+        let msg = ExecuteMsg::Pause { operations: None };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &coins(100, "uusd")),
+            strategy,
+        );
+        assert!(res.is_err());
+
+        // queries keep working while paused
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+        let value: ConfigResponse = from_binary(&res).unwrap();
+        assert!(value.paused);
+        assert_eq!(Some(Addr::unchecked("guardian")), value.guardian);
+
+        // a cleared guardian can no longer pause
+        let msg = ExecuteMsg::UpdateGuardian { guardian: None };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Config {}).unwrap();
+        let value: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(None, value.guardian);
+
+        let msg = ExecuteMsg::Pause {
+            operations: Some(vec!["market_swap".to_string()]),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("guardian", &[]), msg);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn allowlist_restricts_factories_and_tokens() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let step = StrategyStep {
+            from_asset: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            to_asset: AssetInfo::Token {
+                contract_addr: "token".to_string(),
+            },
+            operation: StrategyStepOperation::LiquidityPoolSwapOperation {
+                msg: from_slice(br#"{"factory_addr":"factory"}"#).unwrap(),
+            },
+            max_oracle_deviation: None,
+        };
+        let register = ExecuteMsg::RegisterStrategy {
+            name: "mine".to_string(),
+            steps: vec![step],
+            global: None,
+            max_spread: None,
+        };
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            register.clone(),
+        );
+        assert!(res.is_err());
+
+        let msg = ExecuteMsg::UpdateAllowlist {
+            kind: AllowlistKind::Token,
+            add: vec!["token".to_string()],
+            remove: vec![],
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        // the factory is checked before it is ever queried
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            register.clone(),
+        );
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("factory not allowed: factory"));
+        let msg = ExecuteMsg::UpdateAllowlist {
+            kind: AllowlistKind::Factory,
+            add: vec!["factory".to_string()],
+            remove: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            register.clone(),
+        )
+        .unwrap();
+
+        let msg = QueryMsg::Allowlist {
+            kind: AllowlistKind::Token,
+            start_after: None,
+            limit: None,
+        };
+        let res: AllowlistResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(vec![Addr::unchecked("token")], res.addresses);
+
+        let msg = ExecuteMsg::UpdateAllowlist {
+            kind: AllowlistKind::Token,
+            add: vec![],
+            remove: vec!["token".to_string()],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            register.clone(),
+        );
+        assert!(res.is_err());
+
+        // permissionless mode skips the allowlists
+        let msg = ExecuteMsg::UpdateConfig {
+            owner: None,
+            permissionless: Some(true),
+            executor_access: None,
+            market_denoms: None,
+            order_bounty: None,
+            dca_bounty: None,
+            arbitrage_bounty: None,
+            flash_loan_fee: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("bot", &[]), register).unwrap();
+    }
+
+    #[test]
+    fn non_executors_can_not_spend_the_inventory() {
+        let mut deps = mock_dependencies(&coins(1000, "uusd"));
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let strategy = ExecuteMsg::ExecuteStrategy {
+            steps: vec![market_step("uusd", "ukrw")],
+            minimum_receive: Uint128::new(1),
+        };
+        let best_strategy = ExecuteMsg::ExecuteBestStrategy {
+            candidates: vec![vec![market_step("uusd", "ukrw")]],
+            minimum_receive: Uint128::new(1),
+        };
+
+        // the executors spend the inventory through inventory strategies only
+        for sender in &["thief", "creator"] {
+            for funds in &[vec![], coins(100, "uluna")] {
+                for msg in &[strategy.clone(), best_strategy.clone()] {
+                    let res = execute(
+                        deps.as_mut(),
+                        mock_env(),
+                        mock_info(sender, funds),
+                        msg.clone(),
+                    );
+                    assert!(res.is_err());
+                }
+            }
+        }
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &coins(100, "uusd")),
+            strategy,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn executor_access_modes() {
+        let mut deps = mock_dependencies(&coins(1000, "uusd"));
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let strategy = ExecuteMsg::ExecuteStrategy {
+            steps: vec![market_step("uusd", "ukrw")],
+            minimum_receive: Uint128::new(1),
+        };
+
+        let msg = ExecuteMsg::UpdateConfig {
+            owner: None,
+            permissionless: None,
+            executor_access: Some(ExecutorAccess::Restricted),
+            market_denoms: None,
+            order_bounty: None,
+            dca_bounty: None,
+            arbitrage_bounty: None,
+            flash_loan_fee: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("user", &coins(100, "uusd")),
+            strategy.clone(),
+        );
+        assert!(res.is_err());
+
+        let msg = ExecuteMsg::UpdateExecutors {
+            add: vec!["bot".to_string()],
+            remove: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        // not even the executors can route without funds
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            strategy.clone(),
+        );
+        assert!(res.is_err());
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &coins(100, "uusd")),
+            strategy.clone(),
+        )
+        .unwrap();
+
+        let msg = QueryMsg::Executors {
+            start_after: None,
+            limit: None,
+        };
+        let res: ExecutorsResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(vec![Addr::unchecked("bot")], res.executors);
+
+        let msg = ExecuteMsg::UpdateConfig {
+            owner: None,
+            permissionless: None,
+            executor_access: Some(ExecutorAccess::InventoryRestricted),
+            market_denoms: None,
+            order_bounty: None,
+            dca_bounty: None,
+            arbitrage_bounty: None,
+            flash_loan_fee: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        // public users can only route the funds they send
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("user", &[]),
+            strategy.clone(),
+        );
+        assert!(res.is_err());
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("user", &coins(100, "uusd")),
+            strategy,
+        )
+        .unwrap();
+
+        // and the first step only spends the sent amount, not the inventory
+        let env = mock_env();
+        let msg = ExecuteMsg::ExecuteStrategyStep {
+            execution_id: 2,
+            step_index: 1,
+            step: market_step("uusd", "ukrw"),
+            to: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(env.contract.address.as_str(), &[]),
+            msg,
+        )
+        .unwrap();
+        assert!(res.attributes.contains(&attr("offer_amount", "100")));
+    }
+
+    #[test]
+    fn inventory_strategy() {
+        let mut deps = crate::testing::mock_dependencies(&coins(1000, "uusd"));
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::Deposit {};
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &coins(10, "uusd")),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+        execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &coins(10, "uusd")),
+            msg,
+        )
+        .unwrap();
+
+        let steps = vec![market_step("uusd", "ukrw"), market_step("ukrw", "uusd")];
+        let msg = ExecuteMsg::ExecuteInventoryStrategy {
+            steps: steps.clone(),
+            amount: Uint128::new(500),
+            minimum_profit: Uint128::new(5),
+        };
+
+        // only executors can spend the inventory
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+
+        // strategies must be circular and cannot spend more than the inventory
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            ExecuteMsg::ExecuteInventoryStrategy {
+                steps: vec![market_step("uusd", "ukrw")],
+                amount: Uint128::new(500),
+                minimum_profit: Uint128::new(5),
+            },
+        );
+        assert!(res.is_err());
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            ExecuteMsg::ExecuteInventoryStrategy {
+                steps: steps.clone(),
+                amount: Uint128::new(5000),
+                minimum_profit: Uint128::new(5),
+            },
+        );
+        assert!(res.is_err());
+
+        let env = mock_env();
+        let res = execute(deps.as_mut(), env.clone(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(3, res.messages.len());
+        assert_eq!(
+            res.messages[1].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: env.contract.address.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::ExecuteStrategyStep {
+                    execution_id: 1,
+                    step_index: 2,
+                    step: steps[1].clone(),
+                    to: None,
+                })
+                .unwrap(),
+            })
+        );
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: env.contract.address.to_string(),
+                funds: vec![],
+                msg: to_binary(&ExecuteMsg::FinalizeStrategy {
+                    execution_id: 1,
+                    receiver: env.contract.address.to_string(),
+                    asset_info: steps[1].to_asset.clone(),
+                    initial_balance: Uint128::new(1000),
+                    minimum_receive: Uint128::new(5),
+                })
+                .unwrap(),
+            })
+        );
+
+        let msg = ExecuteMsg::Withdraw {
+            asset: Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+                amount: Uint128::new(10),
+            },
+            recipient: None,
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+    }
+
+    #[test]
+    fn flash_loans_revert_without_the_lender_callback() {
+        let mut deps = crate::testing::mock_dependencies(&[]);
+        deps.querier
+            .with_flash_lender("lender", Decimal::permille(3));
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::UpdateFlashLender {
+            flash_lender: Some("lender".to_string()),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = ExecuteMsg::ExecuteFlashLoanStrategy {
+            steps: vec![market_step("uusd", "ukrw"), market_step("ukrw", "uusd")],
+            amount: Uint128::new(1000),
+            minimum_profit: Uint128::zero(),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("creator", &[]),
+            msg.clone(),
+        )
+        .unwrap();
+        assert_eq!(2, res.messages.len());
+
+        // the lender returned without calling back, the loan is still stored
+        let env = mock_env();
+        let res = execute(
+            deps.as_mut(),
+            env.clone(),
+            mock_info(env.contract.address.as_str(), &[]),
+            ExecuteMsg::AssertFlashLoanRepaid {},
+        );
+        assert!(res.is_err());
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg);
+        assert!(res
+            .unwrap_err()
+            .to_string()
+            .contains("flash loan already in progress"));
+    }
+
+    #[test]
+    fn sweep_and_query_balances() {
+        let mut deps = mock_dependencies(&coins(1000, "uluna"));
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = QueryMsg::Balances { tokens: None };
+        let res: BalancesResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(
+            vec![Asset {
+                info: AssetInfo::NativeToken {
+                    denom: "uluna".to_string()
+                },
+                amount: Uint128::new(1000),
+            }],
+            res.balances
+        );
+
+        let msg = ExecuteMsg::Sweep {
+            assets: vec![
+                AssetInfo::NativeToken {
+                    denom: "uluna".to_string(),
+                },
+                AssetInfo::NativeToken {
+                    denom: "uusd".to_string(),
+                },
+            ],
+            recipient: Some("treasury".to_string()),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+
+        // empty balances are skipped
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+        assert_eq!(1, res.messages.len());
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "treasury".to_string(),
+                amount: coins(1000, "uluna"),
+            })
+        );
+    }
+
+    #[test]
+    fn simulate_strategy() {
+        // 1 luna = 2 ust = 4 krw
+        let mut deps = crate::testing::mock_dependencies(&[]);
+        deps.querier.with_exchange_rates(&[
+            ("uusd", Decimal::from_ratio(2u128, 1u128)),
+            ("ukrw", Decimal::from_ratio(4u128, 1u128)),
+        ]);
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = QueryMsg::SimulateStrategy {
+            steps: vec![market_step("uluna", "uusd"), market_step("uusd", "ukrw")],
+            amount: Uint128::new(100),
+        };
+        let res: SimulateStrategyResponse =
+            from_binary(&query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(vec![Uint128::new(200), Uint128::new(400)], res.amounts);
+        assert_eq!(Uint128::new(400), res.return_amount);
+
+        let msg = QueryMsg::SimulateStrategy {
+            steps: vec![market_step("uluna", "uusd"), market_step("ukrw", "uluna")],
+            amount: Uint128::new(100),
+        };
+        assert!(query(deps.as_ref(), mock_env(), msg).is_err());
+    }
+
+    #[test]
+    fn update_reference_price() {
+        let mut deps = mock_dependencies(&[]);
+        let msg = InstantiateMsg { comission: 6 };
+        instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let price = ReferencePrice {
+            denom: "uusd".to_string(),
+            price: Decimal::percent(250),
+        };
+        let msg = ExecuteMsg::UpdateReferencePrice {
+            token: "mirror".to_string(),
+            price: Some(price.clone()),
+        };
+        let res = execute(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("bot", &[]),
+            msg.clone(),
+        );
+        assert!(res.is_err());
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg).unwrap();
+
+        let msg = QueryMsg::ReferencePrice {
+            token: "mirror".to_string(),
+        };
+        let res: ReferencePrice =
+            from_binary(&query(deps.as_ref(), mock_env(), msg.clone()).unwrap()).unwrap();
+        assert_eq!(price, res);
+
+        let zero = ExecuteMsg::UpdateReferencePrice {
+            token: "mirror".to_string(),
+            price: Some(ReferencePrice {
+                denom: "uusd".to_string(),
+                price: Decimal::zero(),
+            }),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), zero);
+        assert!(res.is_err());
+
+        let remove = ExecuteMsg::UpdateReferencePrice {
+            token: "mirror".to_string(),
+            price: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), remove).unwrap();
+        assert!(query(deps.as_ref(), mock_env(), msg).is_err());
+    }
 }
@@ -0,0 +1,369 @@
+use cosmwasm_std::{
+    to_binary, Addr, CosmosMsg, Decimal, Deps, DepsMut, Env, MessageInfo, Order, Response,
+    StdError, StdResult, Storage, Uint128, WasmMsg,
+};
+use cw_storage_plus::{Bound, U64Key};
+use terra_cosmwasm::TerraMsgWrapper;
+
+use crate::asset::Asset;
+use crate::contract::{
+    assert_can_execute, assert_not_paused, assert_operations_not_paused, assert_valid_steps,
+    execute_strategy, simulate_steps,
+};
+use crate::escrow::{add_escrow, remove_escrow};
+use crate::msg::{
+    DcaResponse, DcaRunResponse, DcaRunsResponse, DcasResponse, ExecuteMsg, StrategyStep,
+};
+use crate::operations::StepOperation;
+use crate::querier::query_balance;
+use crate::state::{dcas, Dca, DcaRun, DCA_COUNT, DCA_RUNS, STATE};
+use crate::tax::TaxCache;
+
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+fn load_dca(storage: &dyn Storage, id: u64) -> StdResult<Dca> {
+    dcas()
+        .may_load(storage, U64Key::from(id))?
+        .ok_or_else(|| StdError::generic_err(format!("dca not found: {}", id)))
+}
+
+/// Holds `budget` for the recurring strategy, the budget must already be in the router.
+/// The first run can be triggered right away.
+#[allow(clippy::too_many_arguments)]
+pub fn create_dca(
+    deps: DepsMut,
+    env: Env,
+    owner: Addr,
+    steps: Vec<StrategyStep>,
+    budget: Asset,
+    amount_per_run: Uint128,
+    interval: u64,
+    tolerance: Decimal,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    assert_not_paused(&state)?;
+    assert_operations_not_paused(&state, &steps)?;
+    assert_valid_steps(deps.as_ref(), &steps)?;
+    assert_can_execute(deps.as_ref(), &state, &owner)?;
+
+    let from_asset_info = steps.first().unwrap().get_from_asset();
+    let target_asset_info = steps.last().unwrap().get_to_asset();
+    if !budget.info.equal(&from_asset_info) {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; dca offer asset: {}, sent asset: {}",
+            from_asset_info, budget.info
+        )));
+    }
+    if from_asset_info.equal(&target_asset_info) {
+        return Err(StdError::generic_err(
+            "invalid dca; the route must end on another asset than its budget",
+        ));
+    }
+    if let Some(step) = steps.iter().find(|step| step.operation.uses_inventory()) {
+        return Err(StdError::generic_err(format!(
+            "invalid dca; {} uses the router inventory",
+            step.operation.describe()
+        )));
+    }
+    if amount_per_run.is_zero() || budget.amount < amount_per_run {
+        return Err(StdError::generic_err(format!(
+            "invalid dca; amount per run: {} should be positive and at most the budget: {}",
+            amount_per_run, budget.amount
+        )));
+    }
+    if interval == 0 {
+        return Err(StdError::generic_err(
+            "invalid dca; interval must be positive",
+        ));
+    }
+    if tolerance >= Decimal::one() {
+        return Err(StdError::generic_err(format!(
+            "invalid dca; tolerance: {} should be lower than 1",
+            tolerance
+        )));
+    }
+
+    let id = DCA_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    DCA_COUNT.save(deps.storage, &id)?;
+    dcas().save(
+        deps.storage,
+        U64Key::from(id),
+        &Dca {
+            id,
+            owner: owner.clone(),
+            steps,
+            amount_per_run,
+            remaining: budget.clone(),
+            interval,
+            tolerance,
+            next_run_height: env.block.height,
+            runs: 0,
+        },
+    )?;
+    add_escrow(deps, &budget)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_dca")
+        .add_attribute("dca_id", id.to_string())
+        .add_attribute("owner", owner)
+        .add_attribute("budget", budget.to_string())
+        .add_attribute("amount_per_run", amount_per_run)
+        .add_attribute("interval", interval.to_string()))
+}
+
+/// Spends the next `amount_per_run` of the budget, the output stays in the router
+/// until `RecordDcaRun` pays it to the owner and the keeper bounty to the caller
+pub fn execute_dca(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let mut dca = load_dca(deps.storage, id)?;
+    if env.block.height < dca.next_run_height {
+        return Err(StdError::generic_err(format!(
+            "dca {} can not run before height {}",
+            id, dca.next_run_height
+        )));
+    }
+
+    let offer = Asset {
+        info: dca.remaining.info.clone(),
+        amount: dca.amount_per_run,
+    };
+    let simulated = *simulate_steps(deps.as_ref(), &dca.steps, offer.amount, false)?
+        .last()
+        .unwrap();
+    let minimum_receive = simulated.checked_sub(simulated * dca.tolerance)?;
+
+    dca.remaining.amount = dca.remaining.amount.checked_sub(offer.amount)?;
+    dca.runs += 1;
+    dca.next_run_height = env.block.height + dca.interval;
+    dcas().save(deps.storage, U64Key::from(id), &dca)?;
+    remove_escrow(deps.branch(), &offer)?;
+    DCA_RUNS.save(
+        deps.storage,
+        (U64Key::from(id), U64Key::from(dca.runs)),
+        &DcaRun {
+            run: dca.runs,
+            height: env.block.height,
+            offer_amount: offer.amount,
+            minimum_receive,
+            return_amount: None,
+        },
+    )?;
+
+    let initial_balance = query_balance(
+        &deps.querier,
+        env.contract.address.clone(),
+        dca.steps.last().unwrap().get_to_asset(),
+    )?;
+    let record = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_binary(&ExecuteMsg::RecordDcaRun {
+            id,
+            run: dca.runs,
+            keeper: info.sender.to_string(),
+            initial_balance,
+        })?,
+    });
+
+    let receiver = env.contract.address.clone();
+    Ok(execute_strategy(
+        deps,
+        env,
+        dca.owner,
+        receiver,
+        offer,
+        dca.steps,
+        None,
+        minimum_receive,
+    )?
+    .add_message(record)
+    .add_attribute("dca_id", id.to_string())
+    .add_attribute("run", dca.runs.to_string())
+    .add_attribute("keeper", info.sender))
+}
+
+/// Pays the run output to the owner minus the keeper bounty, the DCA is removed
+/// and what is left of its budget refunded once it can not pay for another run
+pub fn record_dca_run(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+    run: u64,
+    keeper: String,
+    initial_balance: Uint128,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    if env.contract.address != info.sender {
+        return Err(StdError::generic_err(format!(
+            "unauthorized record; expected caller: {}, caller: {}",
+            env.contract.address, info.sender
+        )));
+    }
+
+    let dca = load_dca(deps.storage, id)?;
+    let target_asset_info = dca.steps.last().unwrap().get_to_asset();
+    let balance = query_balance(
+        &deps.querier,
+        env.contract.address,
+        target_asset_info.clone(),
+    )?;
+    let output = balance.checked_sub(initial_balance)?;
+    let bounty = output * STATE.load(deps.storage)?.dca_bounty;
+    let return_amount = output.checked_sub(bounty)?;
+    DCA_RUNS.update(
+        deps.storage,
+        (U64Key::from(id), U64Key::from(run)),
+        |dca_run| -> StdResult<_> {
+            let mut dca_run = dca_run
+                .ok_or_else(|| StdError::generic_err(format!("dca run not found: {}", run)))?;
+            dca_run.return_amount = Some(return_amount);
+            Ok(dca_run)
+        },
+    )?;
+
+    let mut tax = TaxCache::default();
+    let mut response = Response::new()
+        .add_attribute("action", "record_dca_run")
+        .add_attribute("dca_id", id.to_string())
+        .add_attribute("run", run.to_string())
+        .add_attribute("return_amount", return_amount)
+        .add_attribute("bounty", bounty)
+        .add_message(
+            Asset {
+                info: target_asset_info.clone(),
+                amount: return_amount,
+            }
+            .into_msg(&deps.querier, &mut tax, dca.owner.clone())?,
+        );
+    if !bounty.is_zero() {
+        response = response.add_message(
+            Asset {
+                info: target_asset_info,
+                amount: bounty,
+            }
+            .into_msg(&deps.querier, &mut tax, deps.api.addr_validate(&keeper)?)?,
+        );
+    }
+
+    if dca.remaining.amount < dca.amount_per_run {
+        dcas().remove(deps.storage, U64Key::from(id))?;
+        remove_escrow(deps.branch(), &dca.remaining)?;
+        response = response
+            .add_attribute("finished", "true")
+            .add_attribute("refund", dca.remaining.to_string());
+        if !dca.remaining.amount.is_zero() {
+            response =
+                response.add_message(dca.remaining.into_msg(&deps.querier, &mut tax, dca.owner)?);
+        }
+    }
+
+    Ok(response.add_attribute("tax_paid", tax.paid_to_string()))
+}
+
+/// The run history is kept after the recurring strategy is cancelled
+pub fn cancel_dca(
+    mut deps: DepsMut,
+    info: MessageInfo,
+    id: u64,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let dca = load_dca(deps.storage, id)?;
+    if info.sender != dca.owner {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    dcas().remove(deps.storage, U64Key::from(id))?;
+    remove_escrow(deps.branch(), &dca.remaining)?;
+
+    let mut response = Response::new()
+        .add_attribute("action", "cancel_dca")
+        .add_attribute("dca_id", id.to_string())
+        .add_attribute("refund", dca.remaining.to_string());
+    let mut tax = TaxCache::default();
+    if !dca.remaining.amount.is_zero() {
+        response =
+            response.add_message(dca.remaining.into_msg(&deps.querier, &mut tax, dca.owner)?);
+    }
+
+    Ok(response.add_attribute("tax_paid", tax.paid_to_string()))
+}
+
+fn dca_to_response(dca: Dca) -> DcaResponse {
+    DcaResponse {
+        id: dca.id,
+        owner: dca.owner,
+        steps: dca.steps,
+        amount_per_run: dca.amount_per_run,
+        remaining: dca.remaining,
+        interval: dca.interval,
+        tolerance: dca.tolerance,
+        next_run_height: dca.next_run_height,
+        runs: dca.runs,
+    }
+}
+
+pub fn query_dca(deps: Deps, id: u64) -> StdResult<DcaResponse> {
+    Ok(dca_to_response(load_dca(deps.storage, id)?))
+}
+
+pub fn query_dcas(
+    deps: Deps,
+    owner: Option<String>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<DcasResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|id| Bound::exclusive(U64Key::from(id)));
+
+    let dcas = match owner {
+        Some(owner) => {
+            let owner = deps.api.addr_validate(&owner)?;
+            dcas()
+                .idx
+                .owner
+                .prefix(owner.as_bytes().to_vec())
+                .range(deps.storage, start, None, Order::Ascending)
+                .take(limit)
+                .map(|item| item.map(|(_, dca)| dca_to_response(dca)))
+                .collect::<StdResult<Vec<DcaResponse>>>()?
+        }
+        None => dcas()
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, dca)| dca_to_response(dca)))
+            .collect::<StdResult<Vec<DcaResponse>>>()?,
+    };
+
+    Ok(DcasResponse { dcas })
+}
+
+pub fn query_dca_runs(
+    deps: Deps,
+    id: u64,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<DcaRunsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|run| Bound::exclusive(U64Key::from(run)));
+
+    let runs = DCA_RUNS
+        .prefix(U64Key::from(id))
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| {
+            item.map(|(_, run)| DcaRunResponse {
+                run: run.run,
+                height: run.height,
+                offer_amount: run.offer_amount,
+                minimum_receive: run.minimum_receive,
+                return_amount: run.return_amount,
+            })
+        })
+        .collect::<StdResult<Vec<DcaRunResponse>>>()?;
+
+    Ok(DcaRunsResponse { runs })
+}
@@ -0,0 +1,28 @@
+use cosmwasm_std::{Deps, DepsMut, StdResult, Uint128};
+
+use crate::asset::{Asset, AssetInfo};
+use crate::state::ESCROW;
+
+/// Amount of `asset_info` the router holds on behalf of its users
+pub fn escrowed(deps: Deps, asset_info: &AssetInfo) -> StdResult<Uint128> {
+    let key = asset_info.to_raw(deps.api)?;
+    Ok(ESCROW
+        .may_load(deps.storage, key.as_bytes())?
+        .unwrap_or_default())
+}
+
+pub(crate) fn add_escrow(deps: DepsMut, asset: &Asset) -> StdResult<()> {
+    let key = asset.info.to_raw(deps.api)?;
+    ESCROW.update(deps.storage, key.as_bytes(), |escrowed| -> StdResult<_> {
+        Ok(escrowed.unwrap_or_default().checked_add(asset.amount)?)
+    })?;
+    Ok(())
+}
+
+pub(crate) fn remove_escrow(deps: DepsMut, asset: &Asset) -> StdResult<()> {
+    let key = asset.info.to_raw(deps.api)?;
+    ESCROW.update(deps.storage, key.as_bytes(), |escrowed| -> StdResult<_> {
+        Ok(escrowed.unwrap_or_default().checked_sub(asset.amount)?)
+    })?;
+    Ok(())
+}
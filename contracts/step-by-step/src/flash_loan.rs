@@ -0,0 +1,319 @@
+use cosmwasm_std::{
+    to_binary, Binary, Coin, CosmosMsg, DepsMut, Env, MessageInfo, Response, StdError, StdResult,
+    Uint128, WasmMsg,
+};
+use cw20::Cw20ExecuteMsg;
+use terra_cosmwasm::TerraMsgWrapper;
+
+use crate::asset::{Asset, AssetInfo};
+use crate::contract::{
+    assert_not_paused, assert_operations_not_paused, assert_valid_steps, is_executor,
+    query_inventory, step_messages,
+};
+use crate::lender::{query_flash_fee, FlashLenderExecuteMsg};
+use crate::msg::{ExecuteMsg, StrategyStep};
+use crate::querier::query_balance;
+use crate::state::{FlashLoan, FLASH_LOAN, STATE};
+use crate::tax::TaxCache;
+
+/// Borrows `amount` of the first step asset from the flash lender, the steps run once
+/// the lender calls `FlashLoanCallback` and `AssertFlashLoanRepaid` reverts when it did not
+pub fn execute_flash_loan_strategy(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    steps: Vec<StrategyStep>,
+    amount: Uint128,
+    minimum_profit: Uint128,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    assert_not_paused(&state)?;
+    assert_operations_not_paused(&state, &steps)?;
+    if !is_executor(deps.as_ref(), &state, &info.sender) {
+        return Err(StdError::generic_err(format!(
+            "unauthorized; {} is not an executor",
+            info.sender
+        )));
+    }
+    assert_valid_steps(deps.as_ref(), &steps)?;
+
+    let asset_info = steps.first().unwrap().get_from_asset();
+    if !asset_info.equal(&steps.last().unwrap().get_to_asset()) {
+        return Err(StdError::generic_err(
+            "assertion failed; flash loan strategies must start and end on the same asset",
+        ));
+    }
+    // cw20 loans would reach the router through `Receive`, which has no callback hook
+    if !asset_info.is_native_token() {
+        return Err(StdError::generic_err(format!(
+            "invalid steps; flash loans can only borrow native tokens, got {}",
+            asset_info
+        )));
+    }
+    if amount.is_zero() {
+        return Err(StdError::generic_err(
+            "assertion failed; flash loan amount must be positive",
+        ));
+    }
+    let lender = state
+        .flash_lender
+        .ok_or_else(|| StdError::generic_err("no flash lender configured"))?;
+    if FLASH_LOAN.may_load(deps.storage)?.is_some() {
+        return Err(StdError::generic_err("flash loan already in progress"));
+    }
+
+    let asset = Asset {
+        info: asset_info.clone(),
+        amount,
+    };
+    let fee = query_flash_fee(&deps.querier, &lender, &asset)?;
+    let initial_balance = query_balance(&deps.querier, env.contract.address.clone(), asset_info)?;
+    FLASH_LOAN.save(
+        deps.storage,
+        &FlashLoan {
+            lender: lender.clone(),
+            asset: asset.clone(),
+            fee,
+            steps,
+            initial_balance,
+            minimum_profit,
+        },
+    )?;
+
+    Ok(Response::new()
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: lender.to_string(),
+            funds: vec![],
+            msg: to_binary(&FlashLenderExecuteMsg::FlashLoan {
+                asset: asset.clone(),
+                msg: to_binary(&ExecuteMsg::FlashLoanCallback {})?,
+            })?,
+        }))
+        .add_message(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: env.contract.address.to_string(),
+            funds: vec![],
+            msg: to_binary(&ExecuteMsg::AssertFlashLoanRepaid {})?,
+        }))
+        .add_attribute("action", "execute_flash_loan_strategy")
+        .add_attribute("sender", info.sender)
+        .add_attribute("lender", lender)
+        .add_attribute("loan", asset.to_string())
+        .add_attribute("fee", fee))
+}
+
+/// Runs the steps on the loan, repays it and asserts the inventory grew by the minimum profit
+pub fn flash_loan_callback(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let loan = FLASH_LOAN
+        .may_load(deps.storage)?
+        .ok_or_else(|| StdError::generic_err("no flash loan in progress"))?;
+    if info.sender != loan.lender {
+        return Err(StdError::generic_err(format!(
+            "unauthorized callback; expected caller: {}, caller: {}",
+            loan.lender, info.sender
+        )));
+    }
+
+    let balance = query_balance(
+        &deps.querier,
+        env.contract.address.clone(),
+        loan.asset.info.clone(),
+    )?;
+    if balance < loan.initial_balance.checked_add(loan.asset.amount)? {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; flash loan of {} was not received",
+            loan.asset
+        )));
+    }
+
+    let state = STATE.load(deps.storage)?;
+    let (execution_id, mut messages) = step_messages(
+        deps.branch(),
+        &env,
+        loan.asset.amount,
+        loan.steps,
+        state.default_max_spread,
+        None,
+    )?;
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_binary(&ExecuteMsg::RepayFlashLoan {})?,
+    }));
+    messages.push(CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_binary(&ExecuteMsg::FinalizeStrategy {
+            execution_id,
+            receiver: env.contract.address.to_string(),
+            asset_info: loan.asset.info,
+            initial_balance: loan.initial_balance,
+            minimum_receive: loan.minimum_profit,
+        })?,
+    }));
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attribute("action", "flash_loan_callback")
+        .add_attribute("execution_id", execution_id.to_string()))
+}
+
+pub fn repay_flash_loan(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    if env.contract.address != info.sender {
+        return Err(StdError::generic_err(format!(
+            "unauthorized repay; expected caller: {}, caller: {}",
+            env.contract.address, info.sender
+        )));
+    }
+
+    let loan = FLASH_LOAN.load(deps.storage)?;
+    FLASH_LOAN.remove(deps.storage);
+
+    let repayment = Asset {
+        info: loan.asset.info,
+        amount: loan.asset.amount.checked_add(loan.fee)?,
+    };
+    // the router pays the transfer tax on top so the lender receives the whole repayment
+    let mut tax = TaxCache::default();
+    let message = Asset {
+        info: repayment.info.clone(),
+        amount: tax.add_tax(&deps.querier, &repayment)?,
+    }
+    .into_msg(&deps.querier, &mut tax, loan.lender)?;
+
+    Ok(Response::new()
+        .add_message(message)
+        .add_attribute("action", "repay_flash_loan")
+        .add_attribute("repaid", repayment.to_string())
+        .add_attribute("tax_paid", tax.paid_to_string()))
+}
+
+/// Runs after the lender returns, the loan is only removed once `RepayFlashLoan` ran
+pub fn assert_flash_loan_repaid(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    if env.contract.address != info.sender {
+        return Err(StdError::generic_err(format!(
+            "unauthorized assertion; expected caller: {}, caller: {}",
+            env.contract.address, info.sender
+        )));
+    }
+
+    if let Some(loan) = FLASH_LOAN.may_load(deps.storage)? {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; flash loan of {} was not called back by {}",
+            loan.asset, loan.lender
+        )));
+    }
+
+    Ok(Response::new().add_attribute("action", "assert_flash_loan_repaid"))
+}
+
+/// Lends `amount` of the inventory to the calling contract along with its `callback`,
+/// `FinalizeFlashLoan` then asserts the inventory was restored with the fee
+pub fn flash_loan(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    asset_info: AssetInfo,
+    amount: Uint128,
+    callback: Binary,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    assert_not_paused(&state)?;
+
+    let initial_inventory = query_inventory(deps.as_ref(), &env, &asset_info)?;
+    if amount.is_zero() || amount > initial_inventory {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; amount: {}, inventory: {}",
+            amount, initial_inventory
+        )));
+    }
+    let fee = amount * state.flash_loan_fee;
+
+    let loan = match &asset_info {
+        AssetInfo::NativeToken { denom } => CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: info.sender.to_string(),
+            funds: vec![Coin {
+                denom: denom.to_string(),
+                amount,
+            }],
+            msg: callback,
+        }),
+        AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: contract_addr.to_string(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: info.sender.to_string(),
+                amount,
+                msg: callback,
+            })?,
+        }),
+    };
+    let finalize = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_binary(&ExecuteMsg::FinalizeFlashLoan {
+            borrower: info.sender.to_string(),
+            asset_info: asset_info.clone(),
+            initial_inventory,
+            fee,
+        })?,
+    });
+
+    Ok(Response::new()
+        .add_message(loan)
+        .add_message(finalize)
+        .add_attribute("action", "flash_loan")
+        .add_attribute("borrower", info.sender)
+        .add_attribute(
+            "loan",
+            Asset {
+                info: asset_info,
+                amount,
+            }
+            .to_string(),
+        )
+        .add_attribute("fee", fee))
+}
+
+pub fn finalize_flash_loan(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    borrower: String,
+    asset_info: AssetInfo,
+    initial_inventory: Uint128,
+    fee: Uint128,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    if env.contract.address != info.sender {
+        return Err(StdError::generic_err(format!(
+            "unauthorized finalize; expected caller: {}, caller: {}",
+            env.contract.address, info.sender
+        )));
+    }
+
+    let inventory = query_inventory(deps.as_ref(), &env, &asset_info)?;
+    let expected_inventory = initial_inventory.checked_add(fee)?;
+    if inventory < expected_inventory {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; flash loan not repaid, inventory: {}, expected inventory: {}",
+            inventory, expected_inventory
+        )));
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "finalize_flash_loan")
+        .add_attribute("borrower", borrower)
+        .add_attribute("initial_inventory", initial_inventory)
+        .add_attribute("final_inventory", inventory))
+}
@@ -0,0 +1,224 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use cosmwasm_std::{
+    to_binary, Addr, Coin, CosmosMsg, QuerierWrapper, QueryRequest, StdResult, Uint128, WasmMsg,
+    WasmQuery,
+};
+use cw20::Cw20ExecuteMsg;
+
+use crate::msg::{
+    ConfigResponse, Cw20HookMsg, ExecuteMsg, QueryMsg, SimulateStrategyResponse, StrategyStep,
+};
+
+/// StepByStepContract is a wrapper around Addr that provides helpers for
+/// contracts routing through the router, import the crate with `features = ["library"]`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StepByStepContract(pub Addr);
+
+impl StepByStepContract {
+    pub fn addr(&self) -> Addr {
+        self.0.clone()
+    }
+
+    pub fn call<C>(&self, msg: ExecuteMsg, funds: Vec<Coin>) -> StdResult<CosmosMsg<C>>
+    where
+        C: Clone + fmt::Debug + PartialEq + JsonSchema,
+    {
+        Ok(WasmMsg::Execute {
+            contract_addr: self.addr().into(),
+            msg: to_binary(&msg)?,
+            funds,
+        }
+        .into())
+    }
+
+    /// Executes `steps` offering the native `funds`, the result is sent back to the caller
+    pub fn call_execute_strategy<C>(
+        &self,
+        steps: Vec<StrategyStep>,
+        funds: Vec<Coin>,
+        minimum_receive: Uint128,
+    ) -> StdResult<CosmosMsg<C>>
+    where
+        C: Clone + fmt::Debug + PartialEq + JsonSchema,
+    {
+        self.call(
+            ExecuteMsg::ExecuteStrategy {
+                steps,
+                minimum_receive,
+            },
+            funds,
+        )
+    }
+
+    /// Sends `amount` of the cw20 `token` to the router, executing `steps` on receipt
+    pub fn call_execute_strategy_with_cw20<C>(
+        &self,
+        token: &Addr,
+        amount: Uint128,
+        steps: Vec<StrategyStep>,
+        minimum_receive: Uint128,
+    ) -> StdResult<CosmosMsg<C>>
+    where
+        C: Clone + fmt::Debug + PartialEq + JsonSchema,
+    {
+        self.send_cw20(
+            token,
+            amount,
+            Cw20HookMsg::ExecuteStrategy {
+                steps,
+                minimum_receive,
+            },
+        )
+    }
+
+    /// Sends `amount` of the cw20 `token` to the router, executing the saved strategy `id` on receipt
+    pub fn call_execute_strategy_by_id_with_cw20<C>(
+        &self,
+        token: &Addr,
+        amount: Uint128,
+        id: u64,
+        minimum_receive: Uint128,
+    ) -> StdResult<CosmosMsg<C>>
+    where
+        C: Clone + fmt::Debug + PartialEq + JsonSchema,
+    {
+        self.send_cw20(
+            token,
+            amount,
+            Cw20HookMsg::ExecuteStrategyById {
+                id,
+                minimum_receive,
+            },
+        )
+    }
+
+    fn send_cw20<C>(
+        &self,
+        token: &Addr,
+        amount: Uint128,
+        hook: Cw20HookMsg,
+    ) -> StdResult<CosmosMsg<C>>
+    where
+        C: Clone + fmt::Debug + PartialEq + JsonSchema,
+    {
+        Ok(WasmMsg::Execute {
+            contract_addr: token.to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: self.addr().into(),
+                amount,
+                msg: to_binary(&hook)?,
+            })?,
+            funds: vec![],
+        }
+        .into())
+    }
+
+    fn query<T: serde::de::DeserializeOwned>(
+        &self,
+        querier: &QuerierWrapper,
+        msg: &QueryMsg,
+    ) -> StdResult<T> {
+        querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+            contract_addr: self.addr().into(),
+            msg: to_binary(msg)?,
+        }))
+    }
+
+    pub fn query_config(&self, querier: &QuerierWrapper) -> StdResult<ConfigResponse> {
+        self.query(querier, &QueryMsg::Config {})
+    }
+
+    /// Expected output of `steps` when offering `amount` of the first step's asset
+    pub fn query_simulate(
+        &self,
+        querier: &QuerierWrapper,
+        steps: Vec<StrategyStep>,
+        amount: Uint128,
+    ) -> StdResult<SimulateStrategyResponse> {
+        self.query(querier, &QueryMsg::SimulateStrategy { steps, amount })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::AssetInfo;
+    use crate::msgs::market::MarketSwapMsg;
+    use crate::operations::StrategyStepOperation;
+    use cosmwasm_std::{coins, from_binary, Empty};
+    use terra_cosmwasm::TerraMsgWrapper;
+
+    #[test]
+    fn builds_router_messages() {
+        let router = StepByStepContract(Addr::unchecked("router"));
+        let steps = vec![StrategyStep {
+            from_asset: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            to_asset: AssetInfo::NativeToken {
+                denom: "uluna".to_string(),
+            },
+            operation: StrategyStepOperation::MarketSwapOperation {
+                msg: MarketSwapMsg {},
+            },
+            max_oracle_deviation: None,
+        }];
+
+        let msg: CosmosMsg<TerraMsgWrapper> = router
+            .call_execute_strategy(steps.clone(), coins(100, "uusd"), Uint128::new(1))
+            .unwrap();
+        match msg {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr,
+                msg,
+                funds,
+            }) => {
+                assert_eq!("router", contract_addr);
+                assert_eq!(coins(100, "uusd"), funds);
+                assert_eq!(
+                    ExecuteMsg::ExecuteStrategy {
+                        steps: steps.clone(),
+                        minimum_receive: Uint128::new(1),
+                    },
+                    from_binary(&msg).unwrap()
+                );
+            }
+            _ => panic!("unexpected message"),
+        }
+
+        let msg: CosmosMsg<Empty> = router
+            .call_execute_strategy_by_id_with_cw20(
+                &Addr::unchecked("token"),
+                Uint128::new(100),
+                1,
+                Uint128::new(1),
+            )
+            .unwrap();
+        match msg {
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr,
+                msg,
+                funds,
+            }) => {
+                assert_eq!("token", contract_addr);
+                assert!(funds.is_empty());
+                assert_eq!(
+                    Cw20ExecuteMsg::Send {
+                        contract: "router".to_string(),
+                        amount: Uint128::new(100),
+                        msg: to_binary(&Cw20HookMsg::ExecuteStrategyById {
+                            id: 1,
+                            minimum_receive: Uint128::new(1),
+                        })
+                        .unwrap(),
+                    },
+                    from_binary(&msg).unwrap()
+                );
+            }
+            _ => panic!("unexpected message"),
+        }
+    }
+}
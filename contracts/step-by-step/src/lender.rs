@@ -0,0 +1,44 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    to_binary, Addr, Binary, QuerierWrapper, QueryRequest, StdResult, Uint128, WasmQuery,
+};
+
+use crate::asset::Asset;
+
+/// Messages a flash lender handles
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashLenderExecuteMsg {
+    /// Sends `asset` to the caller and executes `msg` on it,
+    /// the caller must have sent back `asset` plus the flash fee once `msg` returns
+    FlashLoan { asset: Asset, msg: Binary },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashLenderQueryMsg {
+    /// Fee charged on top of the principal when borrowing `asset`
+    FlashFee { asset: Asset },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FlashFeeResponse {
+    pub fee: Uint128,
+}
+
+pub fn query_flash_fee(
+    querier: &QuerierWrapper,
+    lender: &Addr,
+    asset: &Asset,
+) -> StdResult<Uint128> {
+    let response: FlashFeeResponse = querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: lender.to_string(),
+        msg: to_binary(&FlashLenderQueryMsg::FlashFee {
+            asset: asset.clone(),
+        })?,
+    }))?;
+
+    Ok(response.fee)
+}
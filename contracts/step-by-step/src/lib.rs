@@ -1,10 +1,23 @@
+pub mod allowlist;
+pub mod arbitrage;
 pub mod asset;
 pub mod contract;
+pub mod dca;
 mod error;
+pub mod escrow;
+pub mod flash_loan;
+pub mod helpers;
+pub mod lender;
 pub mod msg;
 pub mod msgs;
 pub mod operations;
+pub mod oracle;
+pub mod orders;
 pub mod querier;
+pub mod routes;
 pub mod state;
+pub mod tax;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod testing;
 
 pub use crate::error::ContractError;
@@ -1,11 +1,11 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::Uint128;
-use cw20::Cw20ReceiveMsg;
+use cosmwasm_std::{Addr, Binary, Decimal, Deps, StdError, StdResult, Uint128};
+use cw20::{Cw20ReceiveMsg, Expiration};
 
-use crate::asset::AssetInfo;
-use crate::operations::StrategyStepOperation;
+use crate::asset::{Asset, AssetInfo};
+use crate::operations::{StepOperation, StrategyStepOperation};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InstantiateMsg {
@@ -20,18 +20,237 @@ pub enum ExecuteMsg {
         steps: Vec<StrategyStep>,
         minimum_receive: Uint128,
     },
+    /// Simulates every candidate against the current pools and executes the one returning the most,
+    /// candidates must share their offer and ask assets
+    ExecuteBestStrategy {
+        candidates: Vec<Vec<StrategyStep>>,
+        minimum_receive: Uint128,
+    },
+    RegisterStrategy {
+        name: String,
+        steps: Vec<StrategyStep>,
+        /// global strategies can only be registered by the owner and are executable by anyone
+        global: Option<bool>,
+        /// max spread of the strategy pool swaps that do not set their own,
+        /// the configured default applies when not set
+        max_spread: Option<Decimal>,
+    },
+    ExecuteStrategyById {
+        id: u64,
+        amount: Uint128,
+        minimum_receive: Uint128,
+    },
+    DeleteStrategy {
+        id: u64,
+    },
+    /// Adds the sent funds to the contract inventory
+    Deposit {},
+    Withdraw {
+        asset: Asset,
+        recipient: Option<String>,
+    },
+    /// Sends the whole contract inventory of each asset to the recipient, escrowed funds stay
+    Sweep {
+        assets: Vec<AssetInfo>,
+        recipient: Option<String>,
+    },
+    /// Executes a circular strategy spending `amount` from the contract inventory,
+    /// the inventory must grow by at least `minimum_profit`
+    ExecuteInventoryStrategy {
+        steps: Vec<StrategyStep>,
+        amount: Uint128,
+        minimum_profit: Uint128,
+    },
+    /// Executes a circular strategy starting on a native token on `amount` borrowed from the flash lender,
+    /// after repaying the loan and its fee the inventory must grow by at least `minimum_profit`
+    ExecuteFlashLoanStrategy {
+        steps: Vec<StrategyStep>,
+        amount: Uint128,
+        minimum_profit: Uint128,
+    },
+    UpdateConfig {
+        owner: Option<String>,
+        permissionless: Option<bool>,
+        executor_access: Option<ExecutorAccess>,
+        /// denoms the route finder can swap through the market
+        market_denoms: Option<Vec<String>>,
+        /// share of a filled order output paid to the keeper executing it
+        order_bounty: Option<Decimal>,
+        /// share of a DCA run output paid to the keeper triggering it
+        dca_bounty: Option<Decimal>,
+        /// share of an arbitrage profit paid to the account triggering it
+        arbitrage_bounty: Option<Decimal>,
+
+        /// share of the principal charged on the flash loans the router offers
+        flash_loan_fee: Option<Decimal>,
+    },
+    /// Sets the max spread of the pool swaps that set none, neither in their step nor their strategy,
+    /// clears it when no max spread is given
+    UpdateDefaultMaxSpread {
+        max_spread: Option<Decimal>,
+    },
+    /// Sets the contract flash loan strategies borrow from,
+    /// clears it when no lender is given which disables flash loan strategies
+    UpdateFlashLender {
+        flash_lender: Option<String>,
+    },
+    UpdateExecutors {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    UpdateAllowlist {
+        kind: AllowlistKind,
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+    /// Sets the account allowed to pause the router besides the owner,
+    /// clears it when no guardian is given
+    UpdateGuardian {
+        guardian: Option<String>,
+    },
+    /// Pauses the given operation kinds, or the whole router when none are given
+    Pause {
+        operations: Option<Vec<String>>,
+    },
+    /// Unpauses the given operation kinds, or everything when none are given
+    Unpause {
+        operations: Option<Vec<String>>,
+    },
+    /// Sets the price cw20 `token` is checked against by the oracle price checks,
+    /// removes it when no price is given
+    UpdateReferencePrice {
+        token: String,
+        price: Option<ReferencePrice>,
+    },
+    /// Registers the pairs searched by the route finder, pairs are resolved through their factory
+    /// and removed by contract address
+    UpdatePairs {
+        add: Vec<PairEntry>,
+        remove: Vec<String>,
+    },
+    /// Places a limit order, the native `offer` is held by the router until the order
+    /// is executed or cancelled, cw20 offers are placed through the receive hook
+    PlaceOrder {
+        steps: Vec<StrategyStep>,
+        offer: Asset,
+        min_output: Uint128,
+        expiry: Expiration,
+    },
+    /// Places an order filled once `trigger` is met, `min_output` bounds the slippage of the fill
+    PlaceConditionalOrder {
+        steps: Vec<StrategyStep>,
+        offer: Asset,
+        trigger: OrderTrigger,
+        min_output: Uint128,
+        expiry: Expiration,
+    },
+    /// Fills the order when its route returns at least its minimum output,
+    /// the caller is paid the order bounty
+    ExecuteOrder {
+        id: u64,
+    },
+    /// Fills the conditional order when its trigger is met, the caller is paid the order bounty
+    TriggerOrder {
+        id: u64,
+    },
+    /// Refunds the order offer, the owner can cancel at any time and anyone once it expired
+    CancelOrder {
+        id: u64,
+    },
+    /// Registers a recurring strategy spending the sent native budget `amount_per_run` at a time
+    /// every `interval` blocks, cw20 budgets are sent through the receive hook
+    CreateDca {
+        steps: Vec<StrategyStep>,
+        budget: Asset,
+        amount_per_run: Uint128,
+        interval: u64,
+        /// share of the simulated output a run may miss
+        tolerance: Decimal,
+    },
+    /// Runs the recurring strategy once its interval passed, anyone can trigger it
+    ExecuteDca {
+        id: u64,
+    },
+    /// Refunds the remaining budget to the owner and removes the recurring strategy
+    CancelDca {
+        id: u64,
+    },
+    /// Registers a circular strategy run on the router inventory, only the owner can register it
+    RegisterArbitrage {
+        steps: Vec<StrategyStep>,
+        /// inventory growth required from every run, after the bounty
+        minimum_profit: Uint128,
+    },
+    DeleteArbitrage {
+        id: u64,
+    },
+    /// Runs the registered arbitrage with `amount` of the inventory, anyone can trigger it
+    /// and is paid the arbitrage bounty out of the profit
+    TriggerArbitrage {
+        id: u64,
+        amount: Uint128,
+    },
     /* INTERNAL USE ONLY */
     ExecuteStrategyStep {
+        execution_id: u64,
+        step_index: u32,
         step: StrategyStep,
         to: Option<String>,
     },
     /* INTERNAL USE ONLY */
     FinalizeStrategy {
+        execution_id: u64,
         receiver: String,
         asset_info: AssetInfo,
         initial_balance: Uint128,
         minimum_receive: Uint128,
     },
+    /* INTERNAL USE ONLY */
+    SettleOrder {
+        id: u64,
+        owner: String,
+        keeper: String,
+        asset_info: AssetInfo,
+        initial_balance: Uint128,
+        min_output: Uint128,
+    },
+    /* INTERNAL USE ONLY */
+    RecordDcaRun {
+        id: u64,
+        run: u64,
+        keeper: String,
+        initial_balance: Uint128,
+    },
+    /// Lends `amount` of the router inventory to the calling contract, native loans are sent
+    /// along with the `callback` execution and cw20 loans through `Send`.
+    /// The inventory must be restored with the flash loan fee once the callback returns,
+    /// the transfer tax of the loan included
+    FlashLoan {
+        asset: AssetInfo,
+        amount: Uint128,
+        callback: Binary,
+    },
+    /// Called back by the flash lender once it sent the loan
+    FlashLoanCallback {},
+    /* INTERNAL USE ONLY */
+    RepayFlashLoan {},
+    /* INTERNAL USE ONLY */
+    AssertFlashLoanRepaid {},
+    /* INTERNAL USE ONLY */
+    FinalizeFlashLoan {
+        borrower: String,
+        asset_info: AssetInfo,
+        initial_inventory: Uint128,
+        fee: Uint128,
+    },
+    /* INTERNAL USE ONLY */
+    SettleArbitrage {
+        id: u64,
+        keeper: String,
+        asset_info: AssetInfo,
+        initial_balance: Uint128,
+        minimum_profit: Uint128,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -41,6 +260,97 @@ pub enum Cw20HookMsg {
         steps: Vec<StrategyStep>,
         minimum_receive: Uint128,
     },
+    ExecuteBestStrategy {
+        candidates: Vec<Vec<StrategyStep>>,
+        minimum_receive: Uint128,
+    },
+    ExecuteStrategyById {
+        id: u64,
+        minimum_receive: Uint128,
+    },
+    Deposit {},
+    PlaceOrder {
+        steps: Vec<StrategyStep>,
+        min_output: Uint128,
+        expiry: Expiration,
+    },
+    PlaceConditionalOrder {
+        steps: Vec<StrategyStep>,
+        trigger: OrderTrigger,
+        min_output: Uint128,
+        expiry: Expiration,
+    },
+    CreateDca {
+        steps: Vec<StrategyStep>,
+        amount_per_run: Uint128,
+        interval: u64,
+        tolerance: Decimal,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AllowlistKind {
+    Factory,
+    Pair,
+    Token,
+}
+
+/// Who is allowed to execute strategies, strategies spending the contract inventory
+/// are always limited to the owner and the allowlisted executors
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutorAccess {
+    /// only the owner and the allowlisted executors can execute strategies, even with their own funds
+    Restricted,
+    /// anyone can route the funds they send while only the owner and the allowlisted executors
+    /// spend the contract inventory, the default
+    InventoryRestricted,
+}
+
+/// Side of the threshold a trigger fires on, the threshold itself included
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerCondition {
+    /// take profit
+    Above,
+    /// stop loss
+    Below,
+}
+
+impl TriggerCondition {
+    pub fn is_met<T: PartialOrd>(&self, value: T, threshold: T) -> bool {
+        match self {
+            TriggerCondition::Above => value >= threshold,
+            TriggerCondition::Below => value <= threshold,
+        }
+    }
+}
+
+/// Condition a conditional order waits for before it can be triggered
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderTrigger {
+    /// oracle exchange rate of `base_denom` in `quote_denom`
+    ExchangeRate {
+        base_denom: String,
+        quote_denom: String,
+        condition: TriggerCondition,
+        rate: Decimal,
+    },
+    /// simulated output of the order route for the order offer
+    RouteOutput {
+        condition: TriggerCondition,
+        amount: Uint128,
+    },
+}
+
+/// Price of a cw20 token in units of a native denom
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ReferencePrice {
+    pub denom: String,
+    pub price: Decimal,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -49,15 +359,44 @@ pub struct StrategyStep {
     pub from_asset: AssetInfo,
     pub to_asset: AssetInfo,
     pub operation: StrategyStepOperation,
+    /// when set, the step fails if its simulated rate is worse than the oracle rate by more than this ratio
+    pub max_oracle_deviation: Option<Decimal>,
 }
 
 impl StrategyStep {
     pub fn get_from_asset(&self) -> AssetInfo {
-        return self.from_asset.clone();
+        self.from_asset.clone()
     }
 
     pub fn get_to_asset(&self) -> AssetInfo {
-        return self.to_asset.clone();
+        self.to_asset.clone()
+    }
+
+    pub fn validate(&self, deps: Deps) -> StdResult<()> {
+        for asset_info in [&self.from_asset, &self.to_asset].iter() {
+            if let AssetInfo::Token { contract_addr } = asset_info {
+                deps.api.addr_validate(contract_addr)?;
+            }
+        }
+
+        if self.from_asset.equal(&self.to_asset) {
+            return Err(StdError::generic_err(format!(
+                "invalid step; from asset and to asset are the same: {}",
+                self.from_asset
+            )));
+        }
+
+        if let Some(max_oracle_deviation) = self.max_oracle_deviation {
+            if max_oracle_deviation > Decimal::one() {
+                return Err(StdError::generic_err(format!(
+                    "invalid step; max oracle deviation: {} is greater than 1",
+                    max_oracle_deviation
+                )));
+            }
+        }
+
+        self.operation
+            .validate(deps, &self.from_asset, &self.to_asset)
     }
 }
 
@@ -65,10 +404,221 @@ impl StrategyStep {
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     Config {},
+    Strategy {
+        id: u64,
+    },
+    Strategies {
+        owner: Option<String>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    Allowlist {
+        kind: AllowlistKind,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    Executors {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Native balances of the contract, plus the balances of the given cw20 tokens
+    Balances {
+        tokens: Option<Vec<String>>,
+    },
+    ReferencePrice {
+        token: String,
+    },
+    /// Expected output of every step of a route when offering `amount` of its first asset
+    SimulateStrategy {
+        steps: Vec<StrategyStep>,
+        amount: Uint128,
+    },
+    Pairs {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Routes of at most `max_hops` steps through the registered pairs and market denoms,
+    /// ranked by their simulated output when offering `amount`
+    FindRoutes {
+        offer_asset: AssetInfo,
+        ask_asset: AssetInfo,
+        max_hops: u32,
+        amount: Uint128,
+        limit: Option<u32>,
+    },
+    Order {
+        id: u64,
+    },
+    Orders {
+        owner: Option<String>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    Dca {
+        id: u64,
+    },
+    Dcas {
+        owner: Option<String>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Runs of the recurring strategy, oldest first
+    DcaRuns {
+        id: u64,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    Arbitrage {
+        id: u64,
+    },
+    Arbitrages {
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
 }
 
 // We define a custom struct for each query response
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ConfigResponse {
+    pub owner: Addr,
     pub comission: i16,
+    pub guardian: Option<Addr>,
+    pub paused: bool,
+    pub paused_operations: Vec<String>,
+    pub permissionless: bool,
+    pub executor_access: ExecutorAccess,
+    pub default_max_spread: Option<Decimal>,
+    pub market_denoms: Vec<String>,
+    pub order_bounty: Decimal,
+    pub dca_bounty: Decimal,
+    pub arbitrage_bounty: Decimal,
+    pub flash_lender: Option<Addr>,
+    pub flash_loan_fee: Decimal,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StrategyResponse {
+    pub id: u64,
+    pub name: String,
+    pub owner: Option<Addr>,
+    pub steps: Vec<StrategyStep>,
+    pub max_spread: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StrategiesResponse {
+    pub strategies: Vec<StrategyResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AllowlistResponse {
+    pub kind: AllowlistKind,
+    pub addresses: Vec<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExecutorsResponse {
+    pub executors: Vec<Addr>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct BalancesResponse {
+    pub balances: Vec<Asset>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SimulateStrategyResponse {
+    /// amount received from each step, in order
+    pub amounts: Vec<Uint128>,
+    pub return_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PairEntry {
+    pub factory_addr: String,
+    pub asset_infos: [AssetInfo; 2],
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PairResponse {
+    pub contract_addr: Addr,
+    pub factory_addr: Addr,
+    pub asset_infos: [AssetInfo; 2],
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PairsResponse {
+    pub pairs: Vec<PairResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RouteResponse {
+    pub steps: Vec<StrategyStep>,
+    pub return_amount: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RoutesResponse {
+    /// best route first
+    pub routes: Vec<RouteResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrderResponse {
+    pub id: u64,
+    pub owner: Addr,
+    pub steps: Vec<StrategyStep>,
+    pub offer: Asset,
+    pub min_output: Uint128,
+    pub expiry: Expiration,
+    pub trigger: Option<OrderTrigger>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OrdersResponse {
+    pub orders: Vec<OrderResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DcaResponse {
+    pub id: u64,
+    pub owner: Addr,
+    pub steps: Vec<StrategyStep>,
+    pub amount_per_run: Uint128,
+    pub remaining: Asset,
+    pub interval: u64,
+    pub tolerance: Decimal,
+    pub next_run_height: u64,
+    pub runs: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DcasResponse {
+    pub dcas: Vec<DcaResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DcaRunResponse {
+    pub run: u64,
+    pub height: u64,
+    pub offer_amount: Uint128,
+    pub minimum_receive: Uint128,
+    pub return_amount: Option<Uint128>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DcaRunsResponse {
+    pub runs: Vec<DcaRunResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ArbitrageResponse {
+    pub id: u64,
+    pub steps: Vec<StrategyStep>,
+    pub minimum_profit: Uint128,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ArbitragesResponse {
+    pub arbitrages: Vec<ArbitrageResponse>,
 }
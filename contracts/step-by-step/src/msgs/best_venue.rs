@@ -0,0 +1,240 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::allowlist::{assert_factory_allowed, assert_pair_contract_allowed};
+use crate::asset::{Asset, AssetInfo};
+use crate::msgs::liquidity_pool::{
+    asset_info_to_terraswap_info, asset_to_terraswap_asset, create_swap_message, query_pair_info,
+    query_simulation,
+};
+use crate::operations::{StepOperation, StrategyStepOperation};
+use crate::tax::TaxCache;
+use cosmwasm_std::{
+    to_binary, Decimal, Deps, QueryRequest, Response, StdError, StdResult, Uint128, WasmQuery,
+};
+use terra_cosmwasm::TerraMsgWrapper;
+use terraswap::asset::PairInfo as TerraswapPairInfo;
+use terraswap::pair::QueryMsg as PairQueryMsg;
+use terraswap::pair::SimulationResponse;
+
+/// Where a pair of the best venue swap can be found
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapVenue {
+    /// the pair of the step assets created by the factory
+    Factory { factory_addr: String },
+    /// a pair contract, it must be allowlisted
+    Pair { pair_addr: String },
+}
+
+/// Swaps on whichever venue returns the most when the step executes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct BestVenueSwapMsg {
+    pub venues: Vec<SwapVenue>,
+    /// when not set, the strategy or the router default applies
+    pub max_spread: Option<Decimal>,
+    /// derives the belief price from the simulation of the chosen venue
+    pub auto_belief_price: Option<bool>,
+}
+
+/// Venue chosen for the swap, with its simulation
+struct BestVenue {
+    index: usize,
+    pair_addr: String,
+    offer_amount: Uint128,
+    simulation: SimulationResponse,
+}
+
+impl BestVenueSwapMsg {
+    /// Pair of `venue` swapping between the step assets, `None` when the venue has no such pair,
+    /// the venues were checked against the allowlist by `validate`
+    fn venue_pair(
+        &self,
+        deps: Deps,
+        venue: &SwapVenue,
+        asset_infos: &[AssetInfo; 2],
+    ) -> StdResult<Option<String>> {
+        match venue {
+            SwapVenue::Factory { factory_addr } => {
+                let factory_addr = deps.api.addr_validate(factory_addr)?;
+                // the factory fails the query when it did not create the pair
+                match query_pair_info(&deps.querier, factory_addr, asset_infos) {
+                    Ok(pair_info) => Ok(Some(pair_info.contract_addr)),
+                    Err(_) => Ok(None),
+                }
+            }
+            SwapVenue::Pair { pair_addr } => {
+                let pair_addr = deps.api.addr_validate(pair_addr)?;
+                let pair_info: TerraswapPairInfo =
+                    deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+                        contract_addr: pair_addr.to_string(),
+                        msg: to_binary(&PairQueryMsg::Pair {})?,
+                    }))?;
+                if asset_infos.iter().any(|asset_info| {
+                    !pair_info
+                        .asset_infos
+                        .contains(&asset_info_to_terraswap_info(asset_info))
+                }) {
+                    return Ok(None);
+                }
+                Ok(Some(pair_addr.to_string()))
+            }
+        }
+    }
+
+    /// Simulates the swap on every venue, venues without the pair are skipped
+    /// while the simulation errors fail the step
+    fn best_venue(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+    ) -> StdResult<BestVenue> {
+        let asset_infos = [offer_asset.info.clone(), ask_asset_info.clone()];
+        let offer_amount = offer_asset
+            .amount
+            .checked_sub(tax.compute_tax(&deps.querier, offer_asset)?)?;
+
+        let mut best: Option<BestVenue> = None;
+        for (index, venue) in self.venues.iter().enumerate() {
+            let pair_addr = match self.venue_pair(deps, venue, &asset_infos)? {
+                Some(pair_addr) => pair_addr,
+                None => continue,
+            };
+            let simulation = query_simulation(
+                &deps.querier,
+                pair_addr.clone(),
+                asset_to_terraswap_asset(offer_asset, Some(offer_amount)),
+            )?;
+            // the first venue wins ties
+            let better = match &best {
+                Some(best) => simulation.return_amount > best.simulation.return_amount,
+                None => true,
+            };
+            if better {
+                best = Some(BestVenue {
+                    index,
+                    pair_addr,
+                    offer_amount,
+                    simulation,
+                });
+            }
+        }
+
+        best.ok_or_else(|| {
+            StdError::generic_err(format!(
+                "no venue has a {} to {} pair",
+                offer_asset.info, ask_asset_info
+            ))
+        })
+    }
+}
+
+impl StepOperation for BestVenueSwapMsg {
+    fn validate(
+        &self,
+        deps: Deps,
+        _offer_asset_info: &AssetInfo,
+        _ask_asset_info: &AssetInfo,
+    ) -> StdResult<()> {
+        if self.venues.is_empty() {
+            return Err(StdError::generic_err("invalid step; no venues"));
+        }
+
+        for venue in self.venues.iter() {
+            match venue {
+                SwapVenue::Factory { factory_addr } => assert_factory_allowed(deps, factory_addr)?,
+                SwapVenue::Pair { pair_addr } => {
+                    assert_pair_contract_allowed(deps, &deps.api.addr_validate(pair_addr)?)?
+                }
+            };
+        }
+
+        if let Some(max_spread) = self.max_spread {
+            if max_spread > Decimal::one() {
+                return Err(StdError::generic_err(format!(
+                    "invalid step; max spread: {} is greater than 1",
+                    max_spread
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve(
+        &self,
+        _deps: Deps,
+        _tax: &mut TaxCache,
+        _offer_asset: &Asset,
+        _ask_asset_info: &AssetInfo,
+        max_spread: Option<Decimal>,
+    ) -> StdResult<StrategyStepOperation> {
+        Ok(StrategyStepOperation::BestVenueSwapOperation {
+            msg: Self {
+                max_spread: self.max_spread.or(max_spread),
+                ..self.clone()
+            },
+        })
+    }
+
+    fn simulate(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+        _sends_out: bool,
+    ) -> StdResult<Uint128> {
+        let best = self.best_venue(deps, tax, offer_asset, ask_asset_info)?;
+        let return_asset = Asset {
+            info: ask_asset_info.clone(),
+            amount: best.simulation.return_amount,
+        };
+
+        Ok(return_asset
+            .amount
+            .checked_sub(tax.compute_tax(&deps.querier, &return_asset)?)?)
+    }
+
+    fn create_execution_message(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: Asset,
+        ask_asset_info: AssetInfo,
+        to: Option<String>,
+    ) -> StdResult<Response<TerraMsgWrapper>> {
+        let best = self.best_venue(deps, tax, &offer_asset, &ask_asset_info)?;
+        let belief_price = if self.auto_belief_price.unwrap_or(false) {
+            // the pair checks the spread against the return before its commission
+            let ask_amount = best.simulation.return_amount + best.simulation.commission_amount;
+            if ask_amount.is_zero() {
+                return Err(StdError::generic_err(format!(
+                    "assertion failed; {} to {} simulation returns nothing",
+                    offer_asset.info, ask_asset_info
+                )));
+            }
+            Some(Decimal::from_ratio(best.offer_amount, ask_amount))
+        } else {
+            None
+        };
+
+        let message = create_swap_message(
+            deps,
+            tax,
+            best.pair_addr.clone(),
+            offer_asset,
+            belief_price,
+            self.max_spread,
+            to,
+        )?;
+
+        Ok(Response::new()
+            .add_message(message)
+            .add_attribute("pair_address", best.pair_addr)
+            .add_attribute("venue", best.index.to_string()))
+    }
+}
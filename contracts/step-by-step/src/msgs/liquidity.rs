@@ -0,0 +1,600 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::allowlist::assert_factory_allowed;
+use crate::asset::{Asset, AssetInfo};
+use crate::msgs::liquidity_pool::{
+    asset_info_to_terraswap_info, asset_to_terraswap_asset, query_pair_info, LiquidityPoolSwapMsg,
+};
+use crate::operations::{StepOperation, StrategyStepOperation};
+use crate::tax::TaxCache;
+use cosmwasm_std::{
+    to_binary, BankMsg, Coin, CosmosMsg, Decimal, Deps, QuerierWrapper, QueryRequest, Response,
+    StdError, StdResult, Uint128, WasmMsg, WasmQuery,
+};
+use cw20::Cw20ExecuteMsg;
+use terra_cosmwasm::TerraMsgWrapper;
+use terraswap::asset::PairInfo as TerraswapPairInfo;
+use terraswap::pair::Cw20HookMsg as PairCw20HookMsg;
+use terraswap::pair::ExecuteMsg as PairExecuteMsg;
+use terraswap::pair::PoolResponse;
+use terraswap::pair::QueryMsg as PairQueryMsg;
+
+/// Provides the offer to a Terraswap pair, the step receives the pair liquidity token.
+/// Without zap the other side of the pair is taken from the router inventory at the pool ratio.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ProvideLiquidityMsg {
+    pub factory_addr: String,
+    /// the other asset of the pair
+    pub pair_asset: AssetInfo,
+    pub slippage_tolerance: Option<Decimal>,
+    /// swaps half of the offer for the pair asset and provides both sides
+    pub zap: Option<bool>,
+    /// max spread of the zap swap
+    pub max_spread: Option<Decimal>,
+}
+
+/// Withdraws the offered liquidity token from its Terraswap pair, the step receives one side of the pair.
+/// Without zap the pair asset side is left in the router inventory.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct WithdrawLiquidityMsg {
+    pub factory_addr: String,
+    /// the other asset of the pair
+    pub pair_asset: AssetInfo,
+    /// swaps the pair asset side for the received asset
+    pub zap: Option<bool>,
+    /// max spread of the zap swap
+    pub max_spread: Option<Decimal>,
+}
+
+fn query_pool(querier: &QuerierWrapper, pair_contract: &str) -> StdResult<PoolResponse> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pair_contract.to_string(),
+        msg: to_binary(&PairQueryMsg::Pool {})?,
+    }))
+}
+
+fn pool_amount(pool: &PoolResponse, info: &AssetInfo) -> StdResult<Uint128> {
+    let terraswap_info = asset_info_to_terraswap_info(info);
+    pool.assets
+        .iter()
+        .find(|asset| asset.info == terraswap_info)
+        .map(|asset| asset.amount)
+        .ok_or_else(|| StdError::generic_err(format!("asset {} is not in the pool", info)))
+}
+
+/// Pair of `asset_infos`, asserting `liquidity_token` is the pair liquidity token
+fn load_pair(
+    deps: Deps,
+    factory_addr: &str,
+    asset_infos: [AssetInfo; 2],
+    liquidity_token: &AssetInfo,
+) -> StdResult<TerraswapPairInfo> {
+    let pair_info = query_pair_info(
+        &deps.querier,
+        deps.api.addr_validate(factory_addr)?,
+        &asset_infos,
+    )?;
+
+    match liquidity_token {
+        AssetInfo::Token { contract_addr } if *contract_addr == pair_info.liquidity_token => {
+            Ok(pair_info)
+        }
+        _ => Err(StdError::generic_err(format!(
+            "assertion failed; {} is not the liquidity token of pair {}",
+            liquidity_token, pair_info.contract_addr
+        ))),
+    }
+}
+
+fn validate_pair_asset(
+    deps: Deps,
+    factory_addr: &str,
+    pair_asset: &AssetInfo,
+    asset_info: &AssetInfo,
+    max_spread: Option<Decimal>,
+) -> StdResult<()> {
+    assert_factory_allowed(deps, factory_addr)?;
+
+    if pair_asset.equal(asset_info) {
+        return Err(StdError::generic_err(format!(
+            "invalid step; pair asset and step asset are the same: {}",
+            pair_asset
+        )));
+    }
+
+    if let AssetInfo::Token { contract_addr } = pair_asset {
+        deps.api.addr_validate(contract_addr)?;
+    }
+
+    if let Some(max_spread) = max_spread {
+        if max_spread > Decimal::one() {
+            return Err(StdError::generic_err(format!(
+                "invalid step; max spread: {} is greater than 1",
+                max_spread
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Amounts of each side the router spends on a provision and the pool they are provided to
+struct Provision {
+    swap_amount: Uint128,
+    offer_amount: Uint128,
+    pair_amount: Uint128,
+    offer_pool: Uint128,
+    pair_pool: Uint128,
+}
+
+impl ProvideLiquidityMsg {
+    fn swap_msg(&self) -> LiquidityPoolSwapMsg {
+        LiquidityPoolSwapMsg {
+            max_spread: self.max_spread,
+            ..LiquidityPoolSwapMsg::new(self.factory_addr.clone())
+        }
+    }
+
+    fn provision(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        pool: &PoolResponse,
+        offer_asset: &Asset,
+    ) -> StdResult<Provision> {
+        let mut offer_pool = pool_amount(pool, &offer_asset.info)?;
+        let mut pair_pool = pool_amount(pool, &self.pair_asset)?;
+        if offer_pool.is_zero() || pair_pool.is_zero() {
+            return Err(StdError::generic_err(
+                "assertion failed; can not provide to a pair without liquidity",
+            ));
+        }
+
+        if self.zap.unwrap_or(false) {
+            let swap_amount = offer_asset.amount.multiply_ratio(1u128, 2u128);
+            let swap_asset = Asset {
+                info: offer_asset.info.clone(),
+                amount: swap_amount,
+            };
+            let (swapped_amount, simulation) =
+                self.swap_msg()
+                    .simulate_swap(deps, tax, &swap_asset, &self.pair_asset)?;
+            let return_asset = Asset {
+                info: self.pair_asset.clone(),
+                amount: simulation.return_amount,
+            };
+            let pair_amount = return_asset
+                .amount
+                .checked_sub(tax.compute_tax(&deps.querier, &return_asset)?)?;
+
+            offer_pool += swapped_amount;
+            pair_pool = pair_pool.checked_sub(simulation.return_amount)?;
+            return Ok(Provision {
+                swap_amount,
+                offer_amount: offer_asset.amount.checked_sub(swap_amount)?,
+                pair_amount,
+                offer_pool,
+                pair_pool,
+            });
+        }
+
+        let provided = offer_asset
+            .amount
+            .checked_sub(tax.compute_tax(&deps.querier, offer_asset)?)?;
+        Ok(Provision {
+            swap_amount: Uint128::zero(),
+            offer_amount: offer_asset.amount,
+            pair_amount: provided.multiply_ratio(pair_pool, offer_pool),
+            offer_pool,
+            pair_pool,
+        })
+    }
+}
+
+impl StepOperation for ProvideLiquidityMsg {
+    fn validate(
+        &self,
+        deps: Deps,
+        offer_asset_info: &AssetInfo,
+        ask_asset_info: &AssetInfo,
+    ) -> StdResult<()> {
+        if ask_asset_info.is_native_token() {
+            return Err(StdError::generic_err(
+                "invalid step; provide liquidity receives the pair liquidity token",
+            ));
+        }
+
+        validate_pair_asset(
+            deps,
+            &self.factory_addr,
+            &self.pair_asset,
+            offer_asset_info,
+            self.max_spread,
+        )
+    }
+
+    fn uses_inventory(&self) -> bool {
+        !self.zap.unwrap_or(false)
+    }
+
+    fn resolve(
+        &self,
+        _deps: Deps,
+        _tax: &mut TaxCache,
+        _offer_asset: &Asset,
+        _ask_asset_info: &AssetInfo,
+        max_spread: Option<Decimal>,
+    ) -> StdResult<StrategyStepOperation> {
+        Ok(StrategyStepOperation::ProvideLiquidityOperation {
+            msg: Self {
+                max_spread: self.max_spread.or(max_spread),
+                ..self.clone()
+            },
+        })
+    }
+
+    fn simulate(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+        _sends_out: bool,
+    ) -> StdResult<Uint128> {
+        let pair_info = load_pair(
+            deps,
+            &self.factory_addr,
+            [offer_asset.info.clone(), self.pair_asset.clone()],
+            ask_asset_info,
+        )?;
+        let pool = query_pool(&deps.querier, &pair_info.contract_addr)?;
+        let provision = self.provision(deps, tax, &pool, offer_asset)?;
+
+        // the pair mints the smallest share of the two deposits
+        let mut share = Uint128::MAX;
+        for (info, amount, pool_amount) in [
+            (
+                &offer_asset.info,
+                provision.offer_amount,
+                provision.offer_pool,
+            ),
+            (&self.pair_asset, provision.pair_amount, provision.pair_pool),
+        ]
+        .iter()
+        .cloned()
+        {
+            let deposit = Asset {
+                info: info.clone(),
+                amount,
+            };
+            let deposited = amount.checked_sub(tax.compute_tax(&deps.querier, &deposit)?)?;
+            share = std::cmp::min(
+                share,
+                deposited.multiply_ratio(pool.total_share, pool_amount),
+            );
+        }
+
+        Ok(share)
+    }
+
+    fn create_execution_message(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: Asset,
+        ask_asset_info: AssetInfo,
+        to: Option<String>,
+    ) -> StdResult<Response<TerraMsgWrapper>> {
+        let pair_info = load_pair(
+            deps,
+            &self.factory_addr,
+            [offer_asset.info.clone(), self.pair_asset.clone()],
+            &ask_asset_info,
+        )?;
+
+        let pool = query_pool(&deps.querier, &pair_info.contract_addr)?;
+        let provision = self.provision(deps, tax, &pool, &offer_asset)?;
+
+        let mut response = Response::new();
+        if !provision.swap_amount.is_zero() {
+            let swap = self.swap_msg().create_execution_message(
+                deps,
+                tax,
+                Asset {
+                    info: offer_asset.info.clone(),
+                    amount: provision.swap_amount,
+                },
+                self.pair_asset.clone(),
+                None,
+            )?;
+            response = response.add_submessages(swap.messages);
+        }
+
+        let mut funds: Vec<Coin> = vec![];
+        let mut assets = vec![];
+        for (info, amount) in [
+            (offer_asset.info.clone(), provision.offer_amount),
+            (self.pair_asset.clone(), provision.pair_amount),
+        ]
+        .iter()
+        .cloned()
+        {
+            let deposit = Asset { info, amount };
+            match &deposit.info {
+                AssetInfo::NativeToken { denom } => {
+                    let amount = tax.deduct_tax(&deps.querier, &deposit)?;
+                    funds.push(Coin {
+                        denom: denom.to_string(),
+                        amount,
+                    });
+                    assets.push(asset_to_terraswap_asset(&deposit, Some(amount)));
+                }
+                AssetInfo::Token { contract_addr } => {
+                    response = response.add_message(WasmMsg::Execute {
+                        contract_addr: contract_addr.to_string(),
+                        funds: vec![],
+                        msg: to_binary(&Cw20ExecuteMsg::IncreaseAllowance {
+                            spender: pair_info.contract_addr.clone(),
+                            amount,
+                            expires: None,
+                        })?,
+                    });
+                    assets.push(asset_to_terraswap_asset(&deposit, None));
+                }
+            }
+        }
+        funds.sort_by(|a, b| a.denom.cmp(&b.denom));
+
+        Ok(response
+            .add_message(WasmMsg::Execute {
+                contract_addr: pair_info.contract_addr.clone(),
+                funds,
+                msg: to_binary(&PairExecuteMsg::ProvideLiquidity {
+                    assets: [assets[0].clone(), assets[1].clone()],
+                    slippage_tolerance: self.slippage_tolerance,
+                    receiver: to,
+                })?,
+            })
+            .add_attribute("pair_address", pair_info.contract_addr)
+            .add_attribute("pair_amount", provision.pair_amount))
+    }
+}
+
+impl WithdrawLiquidityMsg {
+    fn swap_msg(&self) -> LiquidityPoolSwapMsg {
+        LiquidityPoolSwapMsg {
+            max_spread: self.max_spread,
+            ..LiquidityPoolSwapMsg::new(self.factory_addr.clone())
+        }
+    }
+
+    /// Amount of `info` the pair takes out of its pool for `share`
+    fn withdrawn(pool: &PoolResponse, info: &AssetInfo, share: Uint128) -> StdResult<Uint128> {
+        if pool.total_share.is_zero() {
+            return Err(StdError::generic_err(
+                "assertion failed; can not withdraw from a pair without liquidity",
+            ));
+        }
+
+        Ok(pool_amount(pool, info)?.multiply_ratio(share, pool.total_share))
+    }
+
+    /// Amount of `info` the router receives back for `share`, after the pair deducted the tax
+    fn refund(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        pool: &PoolResponse,
+        info: &AssetInfo,
+        share: Uint128,
+    ) -> StdResult<Asset> {
+        let refund = Asset {
+            info: info.clone(),
+            amount: Self::withdrawn(pool, info, share)?,
+        };
+        Ok(Asset {
+            amount: refund
+                .amount
+                .checked_sub(tax.compute_tax(&deps.querier, &refund)?)?,
+            info: refund.info,
+        })
+    }
+
+    /// Return of the zap swap of `pair_refund` on the reserves left once `share` is withdrawn,
+    /// the pair commission rate is taken from its simulation on the current reserves
+    fn simulate_zap(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        pool: &PoolResponse,
+        pair_refund: &Asset,
+        ask_asset_info: &AssetInfo,
+        share: Uint128,
+    ) -> StdResult<Uint128> {
+        let (offer_amount, simulation) =
+            self.swap_msg()
+                .simulate_swap(deps, tax, pair_refund, ask_asset_info)?;
+        let commission_rate = Decimal::from_ratio(
+            simulation.commission_amount,
+            std::cmp::max(
+                simulation.return_amount + simulation.commission_amount,
+                Uint128::new(1),
+            ),
+        );
+
+        let offer_pool = pool_amount(pool, &self.pair_asset)?.checked_sub(Self::withdrawn(
+            pool,
+            &self.pair_asset,
+            share,
+        )?)?;
+        let ask_pool = pool_amount(pool, ask_asset_info)?.checked_sub(Self::withdrawn(
+            pool,
+            ask_asset_info,
+            share,
+        )?)?;
+        if offer_pool.is_zero() || ask_pool.is_zero() {
+            return Err(StdError::generic_err(
+                "assertion failed; can not swap on a pair left without liquidity",
+            ));
+        }
+
+        // constant product swap, the commission is taken from the return
+        let return_amount = ask_pool.checked_sub(
+            ask_pool.multiply_ratio(offer_pool, offer_pool.checked_add(offer_amount)?),
+        )?;
+        let return_asset = Asset {
+            info: ask_asset_info.clone(),
+            amount: return_amount.checked_sub(return_amount * commission_rate)?,
+        };
+
+        Ok(return_asset
+            .amount
+            .checked_sub(tax.compute_tax(&deps.querier, &return_asset)?)?)
+    }
+}
+
+impl StepOperation for WithdrawLiquidityMsg {
+    fn validate(
+        &self,
+        deps: Deps,
+        offer_asset_info: &AssetInfo,
+        ask_asset_info: &AssetInfo,
+    ) -> StdResult<()> {
+        if offer_asset_info.is_native_token() {
+            return Err(StdError::generic_err(
+                "invalid step; withdraw liquidity offers the pair liquidity token",
+            ));
+        }
+
+        validate_pair_asset(
+            deps,
+            &self.factory_addr,
+            &self.pair_asset,
+            ask_asset_info,
+            self.max_spread,
+        )
+    }
+
+    fn uses_inventory(&self) -> bool {
+        !self.zap.unwrap_or(false)
+    }
+
+    /// The zap swap sets no belief price, the pair checks `max_spread` on the reserves
+    /// left after the withdrawal
+    fn resolve(
+        &self,
+        _deps: Deps,
+        _tax: &mut TaxCache,
+        _offer_asset: &Asset,
+        _ask_asset_info: &AssetInfo,
+        max_spread: Option<Decimal>,
+    ) -> StdResult<StrategyStepOperation> {
+        Ok(StrategyStepOperation::WithdrawLiquidityOperation {
+            msg: Self {
+                max_spread: self.max_spread.or(max_spread),
+                ..self.clone()
+            },
+        })
+    }
+
+    fn simulate(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+        _sends_out: bool,
+    ) -> StdResult<Uint128> {
+        let pair_info = load_pair(
+            deps,
+            &self.factory_addr,
+            [ask_asset_info.clone(), self.pair_asset.clone()],
+            &offer_asset.info,
+        )?;
+        let pool = query_pool(&deps.querier, &pair_info.contract_addr)?;
+        let refund = self.refund(deps, tax, &pool, ask_asset_info, offer_asset.amount)?;
+        if !self.zap.unwrap_or(false) {
+            return Ok(refund.amount);
+        }
+
+        let pair_refund = self.refund(deps, tax, &pool, &self.pair_asset, offer_asset.amount)?;
+        let swapped = self.simulate_zap(
+            deps,
+            tax,
+            &pool,
+            &pair_refund,
+            ask_asset_info,
+            offer_asset.amount,
+        )?;
+        Ok(refund.amount.checked_add(swapped)?)
+    }
+
+    fn create_execution_message(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: Asset,
+        ask_asset_info: AssetInfo,
+        to: Option<String>,
+    ) -> StdResult<Response<TerraMsgWrapper>> {
+        let pair_info = load_pair(
+            deps,
+            &self.factory_addr,
+            [ask_asset_info.clone(), self.pair_asset.clone()],
+            &offer_asset.info,
+        )?;
+
+        let pool = query_pool(&deps.querier, &pair_info.contract_addr)?;
+        let refund = self.refund(deps, tax, &pool, &ask_asset_info, offer_asset.amount)?;
+
+        let mut response = Response::new().add_message(WasmMsg::Execute {
+            contract_addr: pair_info.liquidity_token.clone(),
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: pair_info.contract_addr.clone(),
+                amount: offer_asset.amount,
+                msg: to_binary(&PairCw20HookMsg::WithdrawLiquidity {})?,
+            })?,
+        });
+
+        if self.zap.unwrap_or(false) {
+            let pair_refund =
+                self.refund(deps, tax, &pool, &self.pair_asset, offer_asset.amount)?;
+            let swap = self.swap_msg().create_execution_message(
+                deps,
+                tax,
+                pair_refund,
+                ask_asset_info,
+                to.clone(),
+            )?;
+            response = response.add_submessages(swap.messages);
+        }
+
+        // the pair refunds the router, the received side is forwarded on the last step
+        if let Some(to) = to {
+            let transfer: CosmosMsg<TerraMsgWrapper> = match &refund.info {
+                AssetInfo::NativeToken { denom } => CosmosMsg::Bank(BankMsg::Send {
+                    to_address: to,
+                    amount: vec![Coin {
+                        denom: denom.to_string(),
+                        amount: tax.deduct_tax(&deps.querier, &refund)?,
+                    }],
+                }),
+                AssetInfo::Token { contract_addr } => CosmosMsg::Wasm(WasmMsg::Execute {
+                    contract_addr: contract_addr.to_string(),
+                    funds: vec![],
+                    msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                        recipient: to,
+                        amount: refund.amount,
+                    })?,
+                }),
+            };
+            response = response.add_message(transfer);
+        }
+
+        Ok(response.add_attribute("pair_address", pair_info.contract_addr))
+    }
+}
@@ -1,10 +1,13 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::allowlist::assert_factory_allowed;
 use crate::asset::{Asset, AssetInfo};
+use crate::operations::{StepOperation, StrategyStepOperation};
+use crate::tax::TaxCache;
 use cosmwasm_std::{
     to_binary, Addr, Coin, CosmosMsg, Decimal, Deps, QuerierWrapper, QueryRequest, StdResult,
-    Uint128, WasmMsg, WasmQuery, Response
+    Uint128, WasmMsg, WasmQuery, Response, StdError
 };
 use cw20::Cw20ExecuteMsg;
 use terra_cosmwasm::TerraMsgWrapper;
@@ -13,16 +16,75 @@ use terraswap::asset::AssetInfo as TerraswapAssetInfo;
 use terraswap::asset::PairInfo as TerraswapPairInfo;
 use terraswap::factory::QueryMsg as FactoryQueryMsg;
 use terraswap::pair::ExecuteMsg as PairExecuteMsg;
+use terraswap::pair::QueryMsg as PairQueryMsg;
+use terraswap::pair::SimulationResponse;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct LiquidityPoolSwapMsg {
-    factory_addr: String,
-    belief_price: Option<Decimal>,
-    max_spread: Option<Decimal>,
+    pub factory_addr: String,
+    pub belief_price: Option<Decimal>,
+    /// when not set, the strategy or the router default applies
+    pub max_spread: Option<Decimal>,
+    /// derives the belief price from a simulation when the step executes
+    pub auto_belief_price: Option<bool>,
+}
+
+impl LiquidityPoolSwapMsg {
+    pub fn new<T: Into<String>>(factory_addr: T) -> Self {
+        LiquidityPoolSwapMsg {
+            factory_addr: factory_addr.into(),
+            belief_price: None,
+            max_spread: None,
+            auto_belief_price: None,
+        }
+    }
+
+    pub fn with_belief_price(mut self, belief_price: Decimal) -> Self {
+        self.belief_price = Some(belief_price);
+        self.auto_belief_price = None;
+        self
+    }
+
+    pub fn with_auto_belief_price(mut self) -> Self {
+        self.belief_price = None;
+        self.auto_belief_price = Some(true);
+        self
+    }
+
+    pub fn with_max_spread(mut self, max_spread: Decimal) -> Self {
+        self.max_spread = Some(max_spread);
+        self
+    }
+
+    /// Simulates the swap on the pair, returns the offer after tax and the pair simulation
+    pub(crate) fn simulate_swap(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+    ) -> StdResult<(Uint128, SimulationResponse)> {
+        let pair_info: TerraswapPairInfo = query_pair_info(
+            &deps.querier,
+            deps.api.addr_validate(&self.factory_addr)?,
+            &[offer_asset.info.clone(), ask_asset_info.clone()],
+        )?;
+
+        let offer_amount = offer_asset
+            .amount
+            .checked_sub(tax.compute_tax(&deps.querier, offer_asset)?)?;
+        let simulation = query_simulation(
+            &deps.querier,
+            pair_info.contract_addr,
+            asset_to_terraswap_asset(offer_asset, Some(offer_amount)),
+        )?;
+
+        Ok((offer_amount, simulation))
+    }
 }
 
-fn asset_to_terraswap_asset(asset: &Asset, amount: Option<Uint128>) -> TerraswapAsset {
+pub(crate) fn asset_to_terraswap_asset(asset: &Asset, amount: Option<Uint128>) -> TerraswapAsset {
     match &asset.info {
         AssetInfo::Token { .. } => TerraswapAsset {
             info: asset_info_to_terraswap_info(&asset.info),
@@ -35,7 +97,7 @@ fn asset_to_terraswap_asset(asset: &Asset, amount: Option<Uint128>) -> Terraswap
     }
 }
 
-fn asset_info_to_terraswap_info(info: &AssetInfo) -> TerraswapAssetInfo {
+pub(crate) fn asset_info_to_terraswap_info(info: &AssetInfo) -> TerraswapAssetInfo {
     match info {
         AssetInfo::Token { contract_addr } => TerraswapAssetInfo::Token {
             contract_addr: contract_addr.to_string(),
@@ -46,7 +108,7 @@ fn asset_info_to_terraswap_info(info: &AssetInfo) -> TerraswapAssetInfo {
     }
 }
 
-fn query_pair_info(
+pub(crate) fn query_pair_info(
     querier: &QuerierWrapper,
     factory_contract: Addr,
     asset_infos: &[AssetInfo; 2],
@@ -62,57 +124,164 @@ fn query_pair_info(
     }))
 }
 
-impl LiquidityPoolSwapMsg {
-    pub fn create_execution_message(
+pub(crate) fn query_simulation(
+    querier: &QuerierWrapper,
+    pair_contract: String,
+    offer_asset: TerraswapAsset,
+) -> StdResult<SimulationResponse> {
+    querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+        contract_addr: pair_contract,
+        msg: to_binary(&PairQueryMsg::Simulation { offer_asset })?,
+    }))
+}
+
+impl StepOperation for LiquidityPoolSwapMsg {
+    fn validate(
+        &self,
+        deps: Deps,
+        _offer_asset_info: &AssetInfo,
+        _ask_asset_info: &AssetInfo,
+    ) -> StdResult<()> {
+        assert_factory_allowed(deps, &self.factory_addr)?;
+
+        if self.auto_belief_price.unwrap_or(false) && self.belief_price.is_some() {
+            return Err(StdError::generic_err(
+                "invalid step; belief price can not be set when it is derived from a simulation",
+            ));
+        }
+
+        if let Some(max_spread) = self.max_spread {
+            if max_spread > Decimal::one() {
+                return Err(StdError::generic_err(format!(
+                    "invalid step; max spread: {} is greater than 1",
+                    max_spread
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies `default_max_spread` when the swap has no max spread of its own,
+    /// and derives the belief price from a simulation when `auto_belief_price` is set
+    fn resolve(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+        default_max_spread: Option<Decimal>,
+    ) -> StdResult<StrategyStepOperation> {
+        let mut msg = self.clone();
+        msg.max_spread = self.max_spread.or(default_max_spread);
+
+        if self.auto_belief_price.unwrap_or(false) {
+            let (offer_amount, simulation) =
+                self.simulate_swap(deps, tax, offer_asset, ask_asset_info)?;
+            // the pair checks the spread against the return before its commission
+            let ask_amount = simulation.return_amount + simulation.commission_amount;
+            if ask_amount.is_zero() {
+                return Err(StdError::generic_err(format!(
+                    "assertion failed; {} to {} simulation returns nothing",
+                    offer_asset.info, ask_asset_info
+                )));
+            }
+
+            msg.belief_price = Some(Decimal::from_ratio(offer_amount, ask_amount));
+        }
+
+        Ok(StrategyStepOperation::LiquidityPoolSwapOperation { msg })
+    }
+
+    fn simulate(
         &self,
         deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+        _sends_out: bool,
+    ) -> StdResult<Uint128> {
+        // the offer is taxed on the way to the pair and the return on the way back
+        let (_, simulation) = self.simulate_swap(deps, tax, offer_asset, ask_asset_info)?;
+        let return_asset = Asset {
+            info: ask_asset_info.clone(),
+            amount: simulation.return_amount,
+        };
+
+        Ok(return_asset
+            .amount
+            .checked_sub(tax.compute_tax(&deps.querier, &return_asset)?)?)
+    }
+
+    fn create_execution_message(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
         offer_asset: Asset,
         ask_asset_info: AssetInfo,
         to: Option<String>,
     ) -> StdResult<Response<TerraMsgWrapper>> {
-        let factory_addr = deps.api.addr_validate(&self.factory_addr)?;
         let pair_info: TerraswapPairInfo = query_pair_info(
             &deps.querier,
-            factory_addr,
+            deps.api.addr_validate(&self.factory_addr)?,
             &[offer_asset.info.clone(), ask_asset_info],
         )?;
 
-        let messages = match offer_asset.info.clone() {
-            AssetInfo::NativeToken { denom } => {
-                // deduct tax first
-                let amount = offer_asset
-                    .amount
-                    .checked_sub(offer_asset.compute_tax(&deps.querier)?)?;
-
-                vec![CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr: pair_info.contract_addr,
-                    funds: vec![Coin { denom, amount }],
-                    msg: to_binary(&PairExecuteMsg::Swap {
-                        offer_asset: asset_to_terraswap_asset(&offer_asset, Some(amount)),
-                        belief_price: self.belief_price,
-                        max_spread: self.max_spread,
-                        to: to,
-                    })?,
-                })]
-            }
-            AssetInfo::Token { contract_addr } => {
-                vec![CosmosMsg::Wasm(WasmMsg::Execute {
-                    contract_addr,
-                    funds: vec![],
-                    msg: to_binary(&Cw20ExecuteMsg::Send {
-                        contract: pair_info.contract_addr,
-                        amount: offer_asset.amount,
-                        msg: to_binary(&PairExecuteMsg::Swap {
-                            offer_asset: asset_to_terraswap_asset(&offer_asset, None),
-                            belief_price: self.belief_price,
-                            max_spread: self.max_spread,
-                            to: to,
-                        })?,
-                    })?,
-                })]
-            },
-        };
+        let message = create_swap_message(
+            deps,
+            tax,
+            pair_info.contract_addr.clone(),
+            offer_asset,
+            self.belief_price,
+            self.max_spread,
+            to,
+        )?;
+
+        Ok(Response::new()
+            .add_message(message)
+            .add_attribute("pair_address", pair_info.contract_addr))
+    }
+}
+
+/// Swap of `offer_asset` on the pair, native offers are sent net of tax
+pub(crate) fn create_swap_message(
+    deps: Deps,
+    tax: &mut TaxCache,
+    pair_addr: String,
+    offer_asset: Asset,
+    belief_price: Option<Decimal>,
+    max_spread: Option<Decimal>,
+    to: Option<String>,
+) -> StdResult<CosmosMsg<TerraMsgWrapper>> {
+    match offer_asset.info.clone() {
+        AssetInfo::NativeToken { denom } => {
+            // deduct tax first
+            let amount = tax.deduct_tax(&deps.querier, &offer_asset)?;
 
-        Ok(Response::new().add_messages(messages))
+            Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: pair_addr,
+                funds: vec![Coin { denom, amount }],
+                msg: to_binary(&PairExecuteMsg::Swap {
+                    offer_asset: asset_to_terraswap_asset(&offer_asset, Some(amount)),
+                    belief_price,
+                    max_spread,
+                    to,
+                })?,
+            }))
+        }
+        AssetInfo::Token { contract_addr } => Ok(CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr,
+            funds: vec![],
+            msg: to_binary(&Cw20ExecuteMsg::Send {
+                contract: pair_addr,
+                amount: offer_asset.amount,
+                msg: to_binary(&PairExecuteMsg::Swap {
+                    offer_asset: asset_to_terraswap_asset(&offer_asset, None),
+                    belief_price,
+                    max_spread,
+                    to,
+                })?,
+            })?,
+        })),
     }
 }
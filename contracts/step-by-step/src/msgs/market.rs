@@ -2,18 +2,81 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::asset::{Asset, AssetInfo};
-use cosmwasm_std::{Coin, Deps, StdResult, StdError, Response, CosmosMsg};
-use terra_cosmwasm::{create_swap_msg, create_swap_send_msg, TerraMsgWrapper};
+use crate::operations::{StepOperation, StrategyStepOperation};
+use crate::tax::TaxCache;
+use cosmwasm_std::{Coin, Decimal, Deps, StdResult, StdError, Response, CosmosMsg, Uint128};
+use terra_cosmwasm::{create_swap_msg, create_swap_send_msg, TerraMsgWrapper, TerraQuerier};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct MarketSwapMsg {
 }
 
-impl MarketSwapMsg {
-    pub fn create_execution_message(
+impl StepOperation for MarketSwapMsg {
+    fn validate(
+        &self,
+        _deps: Deps,
+        offer_asset_info: &AssetInfo,
+        ask_asset_info: &AssetInfo,
+    ) -> StdResult<()> {
+        if !offer_asset_info.is_native_token() || !ask_asset_info.is_native_token() {
+            return Err(StdError::generic_err(
+                "assertion failed; custom tokens not supported",
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn resolve(
+        &self,
+        _deps: Deps,
+        _tax: &mut TaxCache,
+        _offer_asset: &Asset,
+        _ask_asset_info: &AssetInfo,
+        _max_spread: Option<Decimal>,
+    ) -> StdResult<StrategyStepOperation> {
+        Ok(StrategyStepOperation::MarketSwapOperation { msg: self.clone() })
+    }
+
+    fn simulate(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+        sends_out: bool,
+    ) -> StdResult<Uint128> {
+        match (&offer_asset.info, ask_asset_info) {
+            (
+                AssetInfo::NativeToken { denom: offer_denom },
+                AssetInfo::NativeToken { denom: ask_denom },
+            ) => {
+                // swaps into the router are not taxed, swaps sent out pay the tax on their offer
+                let amount = if sends_out {
+                    offer_asset.amount.checked_sub(tax.compute_tax(&deps.querier, offer_asset)?)?
+                } else {
+                    offer_asset.amount
+                };
+                let res = TerraQuerier::new(&deps.querier).query_swap(
+                    Coin {
+                        denom: offer_denom.to_string(),
+                        amount,
+                    },
+                    ask_denom.to_string(),
+                )?;
+                Ok(res.receive.amount)
+            }
+            _ => Err(StdError::generic_err(
+                "assertion failed; custom tokens not supported",
+            )),
+        }
+    }
+
+    fn create_execution_message(
         &self,
         deps: Deps,
+        tax: &mut TaxCache,
         offer_asset: Asset,
         ask_asset_info: AssetInfo,
         to: Option<String>,
@@ -33,15 +96,13 @@ impl MarketSwapMsg {
                         if let Some(to) = to {
                             // if the operation is last, and requires send
                             // deduct tax from the offer_coin
-                            let amount = offer_asset
-                                .amount
-                                .checked_sub(offer_asset.compute_tax(&deps.querier)?)?;
+                            let amount = tax.deduct_tax(&deps.querier, &offer_asset)?;
         
                             vec![create_swap_send_msg(
                                 to,
                                 Coin {
                                     denom: offer_denom,
-                                    amount: amount,
+                                    amount,
                                 },
                                 ask_denom,
                             )]
@@ -61,6 +122,8 @@ impl MarketSwapMsg {
             AssetInfo::Token { .. } => vec![]
         };
 
-        Ok(Response::new().add_messages(messages))
+        Ok(Response::new()
+            .add_messages(messages)
+            .add_attribute("pair_address", "market"))
     }
 }
\ No newline at end of file
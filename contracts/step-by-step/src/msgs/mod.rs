@@ -1,3 +1,5 @@
+pub mod best_venue;
+pub mod liquidity;
 pub mod liquidity_pool;
 pub mod market;
 pub mod nexus;
\ No newline at end of file
@@ -1,35 +1,151 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Response, Deps, StdResult};
+use cosmwasm_std::{Decimal, Deps, Response, StdResult, Uint128};
 use terra_cosmwasm::TerraMsgWrapper;
 
 use crate::asset::{Asset, AssetInfo};
+use crate::msgs::best_venue::BestVenueSwapMsg;
+use crate::msgs::liquidity::{ProvideLiquidityMsg, WithdrawLiquidityMsg};
 use crate::msgs::liquidity_pool::LiquidityPoolSwapMsg;
 use crate::msgs::market::MarketSwapMsg;
+use crate::tax::TaxCache;
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
-#[serde(rename_all = "snake_case")]
-pub enum StrategyStepOperation {
-    LiquidityPoolSwapOperation { msg: LiquidityPoolSwapMsg },
-    MarketSwapOperation { msg: MarketSwapMsg }
-}
+/// Behaviour every protocol a strategy step can go through has to provide.
+/// Adding a protocol means implementing this trait and listing it in `step_operations!`.
+pub trait StepOperation {
+    /// Checks the step before anything is queried, contracts it queries must be allowlisted
+    fn validate(
+        &self,
+        deps: Deps,
+        offer_asset_info: &AssetInfo,
+        ask_asset_info: &AssetInfo,
+    ) -> StdResult<()>;
+
+    /// Operations spending or leaving assets other than the offer and the ask in the router
+    /// can only run in strategies whose output stays in the router
+    fn uses_inventory(&self) -> bool {
+        false
+    }
+
+    /// Fills in the parameters the router derives when the step executes,
+    /// `max_spread` is the strategy or router default
+    fn resolve(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+        max_spread: Option<Decimal>,
+    ) -> StdResult<StrategyStepOperation>;
+
+    /// Amount of `ask_asset_info` the router, or the receiver when `sends_out` is set,
+    /// would hold after swapping `offer_asset`, `sends_out` matching the `to` of the execution
+    fn simulate(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+        sends_out: bool,
+    ) -> StdResult<Uint128>;
 
-impl StrategyStepOperation {
-    pub fn create_execution_message(
+    fn create_execution_message(
         &self,
         deps: Deps,
+        tax: &mut TaxCache,
         offer_asset: Asset,
         ask_asset_info: AssetInfo,
         to: Option<String>,
-    ) -> StdResult<Response<TerraMsgWrapper>> {
-        match self {
-            StrategyStepOperation::LiquidityPoolSwapOperation { msg } => {
-                msg.create_execution_message(deps, offer_asset, ask_asset_info, to)
-            },
-            StrategyStepOperation::MarketSwapOperation { msg } => {
-                msg.create_execution_message(deps, offer_asset, ask_asset_info, to)
+    ) -> StdResult<Response<TerraMsgWrapper>>;
+}
+
+/// Declares `StrategyStepOperation` from its variants and the kind each one is reported
+/// and paused by, so `OPERATION_KINDS` can not drift from the variants
+macro_rules! step_operations {
+    ($($variant:ident { msg: $msg:ty } => $kind:literal,)+) => {
+        #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+        #[serde(rename_all = "snake_case")]
+        pub enum StrategyStepOperation {
+            $($variant { msg: $msg },)+
+        }
+
+        /// Every operation kind, as returned by `StrategyStepOperation::describe`
+        pub const OPERATION_KINDS: &[&str] = &[$($kind),+];
+
+        impl StrategyStepOperation {
+            /// Name of the operation as reported in the step attributes
+            pub fn describe(&self) -> &'static str {
+                match self {
+                    $(StrategyStepOperation::$variant { .. } => $kind,)+
+                }
+            }
+
+            pub fn as_operation(&self) -> &dyn StepOperation {
+                match self {
+                    $(StrategyStepOperation::$variant { msg } => msg,)+
+                }
             }
         }
+    };
+}
+
+step_operations! {
+    LiquidityPoolSwapOperation { msg: LiquidityPoolSwapMsg } => "liquidity_pool_swap",
+    MarketSwapOperation { msg: MarketSwapMsg } => "market_swap",
+    ProvideLiquidityOperation { msg: ProvideLiquidityMsg } => "provide_liquidity",
+    WithdrawLiquidityOperation { msg: WithdrawLiquidityMsg } => "withdraw_liquidity",
+    BestVenueSwapOperation { msg: BestVenueSwapMsg } => "best_venue_swap",
+}
+
+impl StepOperation for StrategyStepOperation {
+    fn validate(
+        &self,
+        deps: Deps,
+        offer_asset_info: &AssetInfo,
+        ask_asset_info: &AssetInfo,
+    ) -> StdResult<()> {
+        self.as_operation()
+            .validate(deps, offer_asset_info, ask_asset_info)
+    }
+
+    fn uses_inventory(&self) -> bool {
+        self.as_operation().uses_inventory()
+    }
+
+    fn resolve(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+        max_spread: Option<Decimal>,
+    ) -> StdResult<StrategyStepOperation> {
+        self.as_operation()
+            .resolve(deps, tax, offer_asset, ask_asset_info, max_spread)
+    }
+
+    fn simulate(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: &Asset,
+        ask_asset_info: &AssetInfo,
+        sends_out: bool,
+    ) -> StdResult<Uint128> {
+        self.as_operation()
+            .simulate(deps, tax, offer_asset, ask_asset_info, sends_out)
+    }
+
+    fn create_execution_message(
+        &self,
+        deps: Deps,
+        tax: &mut TaxCache,
+        offer_asset: Asset,
+        ask_asset_info: AssetInfo,
+        to: Option<String>,
+    ) -> StdResult<Response<TerraMsgWrapper>> {
+        self.as_operation()
+            .create_execution_message(deps, tax, offer_asset, ask_asset_info, to)
     }
 }
@@ -0,0 +1,115 @@
+use cosmwasm_std::{Decimal, Deps, StdError, StdResult, Uint128};
+use terra_cosmwasm::TerraQuerier;
+
+use crate::asset::{Asset, AssetInfo};
+use crate::msg::{ReferencePrice, StrategyStep};
+use crate::operations::StepOperation;
+use crate::state::REFERENCE_PRICES;
+use crate::tax::{TaxCache, DECIMAL_FRACTION};
+
+fn load_reference_price(deps: Deps, token: &str) -> StdResult<ReferencePrice> {
+    REFERENCE_PRICES
+        .may_load(deps.storage, &deps.api.addr_validate(token)?)?
+        .ok_or_else(|| StdError::generic_err(format!("no reference price for {}", token)))
+}
+
+/// Oracle exchange rate of `base_denom` in `quote_denom`
+pub fn oracle_rate(deps: Deps, base_denom: &str, quote_denom: &str) -> StdResult<Decimal> {
+    Ok(TerraQuerier::new(&deps.querier)
+        .query_exchange_rates(base_denom.to_string(), vec![quote_denom.to_string()])?
+        .exchange_rates
+        .into_iter()
+        .find(|rate| rate.quote_denom == quote_denom)
+        .ok_or_else(|| {
+            StdError::generic_err(format!(
+                "no oracle rate for {} in {}",
+                base_denom, quote_denom
+            ))
+        })?
+        .exchange_rate)
+}
+
+/// Converts `amount` of `info` into `denom` at the oracle exchange rate,
+/// cw20 tokens are converted through their reference price.
+fn oracle_value(deps: Deps, info: &AssetInfo, amount: Uint128, denom: &str) -> StdResult<Uint128> {
+    match info {
+        AssetInfo::NativeToken { denom: base_denom } => {
+            if base_denom == denom {
+                return Ok(amount);
+            }
+
+            Ok(amount * oracle_rate(deps, base_denom, denom)?)
+        }
+        AssetInfo::Token { contract_addr } => {
+            let reference = load_reference_price(deps, contract_addr)?;
+            oracle_value(
+                deps,
+                &AssetInfo::NativeToken {
+                    denom: reference.denom,
+                },
+                amount * reference.price,
+                denom,
+            )
+        }
+    }
+}
+
+/// Amount of `ask_asset_info` the oracle values `offer_asset` at
+pub fn oracle_return(
+    deps: Deps,
+    offer_asset: &Asset,
+    ask_asset_info: &AssetInfo,
+) -> StdResult<Uint128> {
+    match ask_asset_info {
+        AssetInfo::NativeToken { denom } => {
+            oracle_value(deps, &offer_asset.info, offer_asset.amount, denom)
+        }
+        AssetInfo::Token { contract_addr } => {
+            let reference = load_reference_price(deps, contract_addr)?;
+            let value = oracle_value(
+                deps,
+                &offer_asset.info,
+                offer_asset.amount,
+                &reference.denom,
+            )?;
+            Ok(value.multiply_ratio(DECIMAL_FRACTION, DECIMAL_FRACTION * reference.price))
+        }
+    }
+}
+
+/// Simulates the step and fails when it returns less than the oracle rate allows, minus `max_deviation`.
+/// The simulation includes the tax, `sends_out` when the step delivers out of the router,
+/// returns the simulated and the oracle rates.
+pub fn assert_oracle_rate(
+    deps: Deps,
+    tax: &mut TaxCache,
+    step: &StrategyStep,
+    offer_asset: &Asset,
+    sends_out: bool,
+    max_deviation: Decimal,
+) -> StdResult<(Decimal, Decimal)> {
+    if offer_asset.amount.is_zero() {
+        return Err(StdError::generic_err(
+            "assertion failed; can not compare a zero offer with the oracle",
+        ));
+    }
+
+    let return_amount =
+        step.operation
+            .simulate(deps, tax, offer_asset, &step.to_asset, sends_out)?;
+    let oracle_amount = oracle_return(deps, offer_asset, &step.to_asset)?;
+    let rate = Decimal::from_ratio(return_amount, offer_asset.amount);
+    let oracle_rate = Decimal::from_ratio(oracle_amount, offer_asset.amount);
+
+    // a better rate than the oracle is never a deviation
+    if return_amount < oracle_amount
+        && Decimal::from_ratio(oracle_amount - return_amount, oracle_amount) > max_deviation
+    {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; {} to {} rate: {} deviates more than {} from oracle rate: {}",
+            step.from_asset, step.to_asset, rate, max_deviation, oracle_rate
+        )));
+    }
+
+    Ok((rate, oracle_rate))
+}
@@ -0,0 +1,406 @@
+use cosmwasm_std::{
+    to_binary, Addr, CosmosMsg, Deps, DepsMut, Env, MessageInfo, Order, Response, StdError,
+    StdResult, Storage, Uint128, WasmMsg,
+};
+use cw20::Expiration;
+use cw_storage_plus::{Bound, U64Key};
+use terra_cosmwasm::TerraMsgWrapper;
+
+use crate::asset::{Asset, AssetInfo};
+use crate::contract::{
+    assert_can_execute, assert_not_paused, assert_operations_not_paused, assert_valid_steps,
+    execute_strategy, simulate_steps,
+};
+use crate::escrow::{add_escrow, remove_escrow};
+use crate::msg::{ExecuteMsg, OrderResponse, OrderTrigger, OrdersResponse, StrategyStep};
+use crate::operations::StepOperation;
+use crate::oracle::oracle_rate;
+use crate::querier::query_balance;
+use crate::state::{orders, LimitOrder, ORDER_COUNT, STATE};
+use crate::tax::TaxCache;
+
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+fn load_order(storage: &dyn Storage, id: u64) -> StdResult<LimitOrder> {
+    orders()
+        .may_load(storage, U64Key::from(id))?
+        .ok_or_else(|| StdError::generic_err(format!("order not found: {}", id)))
+}
+
+fn validate_trigger(trigger: &OrderTrigger) -> StdResult<()> {
+    match trigger {
+        OrderTrigger::ExchangeRate {
+            base_denom,
+            quote_denom,
+            rate,
+            ..
+        } => {
+            if base_denom == quote_denom || rate.is_zero() {
+                return Err(StdError::generic_err(
+                    "invalid trigger; the rate must be positive and between two denoms",
+                ));
+            }
+        }
+        OrderTrigger::RouteOutput { amount, .. } => {
+            if amount.is_zero() {
+                return Err(StdError::generic_err(
+                    "invalid trigger; the route output must be positive",
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Holds `offer` until the order is executed or cancelled, the offer must already be in the router
+#[allow(clippy::too_many_arguments)]
+pub fn place_order(
+    deps: DepsMut,
+    env: Env,
+    owner: Addr,
+    steps: Vec<StrategyStep>,
+    offer: Asset,
+    min_output: Uint128,
+    expiry: Expiration,
+    trigger: Option<OrderTrigger>,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let state = STATE.load(deps.storage)?;
+    assert_not_paused(&state)?;
+    assert_operations_not_paused(&state, &steps)?;
+    assert_valid_steps(deps.as_ref(), &steps)?;
+    assert_can_execute(deps.as_ref(), &state, &owner)?;
+
+    let from_asset_info = steps.first().unwrap().get_from_asset();
+    let target_asset_info = steps.last().unwrap().get_to_asset();
+    if !offer.info.equal(&from_asset_info) {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; order offer asset: {}, sent asset: {}",
+            from_asset_info, offer.info
+        )));
+    }
+    if from_asset_info.equal(&target_asset_info) {
+        return Err(StdError::generic_err(
+            "invalid order; the route must end on another asset than its offer",
+        ));
+    }
+    if let Some(step) = steps.iter().find(|step| step.operation.uses_inventory()) {
+        return Err(StdError::generic_err(format!(
+            "invalid order; {} uses the router inventory",
+            step.operation.describe()
+        )));
+    }
+    if offer.amount.is_zero() || min_output.is_zero() {
+        return Err(StdError::generic_err(
+            "invalid order; offer and minimum output must be positive",
+        ));
+    }
+    if expiry.is_expired(&env.block) {
+        return Err(StdError::generic_err(format!(
+            "invalid order; expiry: {} already passed",
+            expiry
+        )));
+    }
+    if let Some(trigger) = &trigger {
+        validate_trigger(trigger)?;
+    }
+
+    let id = ORDER_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    ORDER_COUNT.save(deps.storage, &id)?;
+    orders().save(
+        deps.storage,
+        U64Key::from(id),
+        &LimitOrder {
+            id,
+            owner: owner.clone(),
+            steps,
+            offer: offer.clone(),
+            min_output,
+            expiry,
+            trigger,
+        },
+    )?;
+    add_escrow(deps, &offer)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "place_order")
+        .add_attribute("order_id", id.to_string())
+        .add_attribute("owner", owner)
+        .add_attribute("offer", offer.to_string())
+        .add_attribute("min_output", min_output))
+}
+
+fn bounty(deps: Deps, output: Uint128) -> StdResult<Uint128> {
+    Ok(output * STATE.load(deps.storage)?.order_bounty)
+}
+
+fn load_open_order(deps: Deps, env: &Env, id: u64) -> StdResult<LimitOrder> {
+    let order = load_order(deps.storage, id)?;
+    if order.expiry.is_expired(&env.block) {
+        return Err(StdError::generic_err(format!("order {} expired", id)));
+    }
+
+    Ok(order)
+}
+
+fn simulate_order(deps: Deps, order: &LimitOrder) -> StdResult<Uint128> {
+    Ok(
+        *simulate_steps(deps, &order.steps, order.offer.amount, false)?
+            .last()
+            .unwrap(),
+    )
+}
+
+pub fn execute_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let order = load_open_order(deps.as_ref(), &env, id)?;
+    if order.trigger.is_some() {
+        return Err(StdError::generic_err(format!(
+            "order {} is conditional, it is filled through trigger_order",
+            id
+        )));
+    }
+
+    fill_order(deps, env, info.sender, order)
+}
+
+/// Fills the conditional order once its trigger is met
+pub fn trigger_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let order = load_open_order(deps.as_ref(), &env, id)?;
+    let (value, threshold, condition) = match &order.trigger {
+        Some(OrderTrigger::ExchangeRate {
+            base_denom,
+            quote_denom,
+            condition,
+            rate,
+        }) => {
+            let oracle_rate = oracle_rate(deps.as_ref(), base_denom, quote_denom)?;
+            (
+                oracle_rate.to_string(),
+                rate.to_string(),
+                condition.is_met(oracle_rate, *rate),
+            )
+        }
+        Some(OrderTrigger::RouteOutput { condition, amount }) => {
+            let output = simulate_order(deps.as_ref(), &order)?;
+            (
+                output.to_string(),
+                amount.to_string(),
+                condition.is_met(output, *amount),
+            )
+        }
+        None => {
+            return Err(StdError::generic_err(format!(
+                "order {} is not conditional",
+                id
+            )))
+        }
+    };
+    if !condition {
+        return Err(StdError::generic_err(format!(
+            "order {} trigger not met; value: {}, threshold: {}",
+            id, value, threshold
+        )));
+    }
+
+    Ok(fill_order(deps, env, info.sender, order)?
+        .add_attribute("trigger_value", value)
+        .add_attribute("trigger_threshold", threshold))
+}
+
+/// Runs the order route into the router, `SettleOrder` then pays the keeper and the owner
+fn fill_order(
+    mut deps: DepsMut,
+    env: Env,
+    keeper: Addr,
+    order: LimitOrder,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let id = order.id;
+    let output = simulate_order(deps.as_ref(), &order)?;
+    let owner_output = output.checked_sub(bounty(deps.as_ref(), output)?)?;
+    if owner_output < order.min_output {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; order {} can not be filled, simulated output: {}, minimum output: {}",
+            id, owner_output, order.min_output
+        )));
+    }
+
+    orders().remove(deps.storage, U64Key::from(id))?;
+    remove_escrow(deps.branch(), &order.offer)?;
+
+    let target_asset_info = order.steps.last().unwrap().get_to_asset();
+    let initial_balance = query_balance(
+        &deps.querier,
+        env.contract.address.clone(),
+        target_asset_info.clone(),
+    )?;
+    let settle = CosmosMsg::Wasm(WasmMsg::Execute {
+        contract_addr: env.contract.address.to_string(),
+        funds: vec![],
+        msg: to_binary(&ExecuteMsg::SettleOrder {
+            id,
+            owner: order.owner.to_string(),
+            keeper: keeper.to_string(),
+            asset_info: target_asset_info,
+            initial_balance,
+            min_output: order.min_output,
+        })?,
+    });
+
+    // the output stays in the router until the order is settled
+    let receiver = env.contract.address.clone();
+    Ok(execute_strategy(
+        deps,
+        env,
+        order.owner,
+        receiver,
+        order.offer,
+        order.steps,
+        None,
+        Uint128::zero(),
+    )?
+    .add_message(settle)
+    .add_attribute("order_id", id.to_string())
+    .add_attribute("keeper", keeper))
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn settle_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+    owner: String,
+    keeper: String,
+    asset_info: AssetInfo,
+    initial_balance: Uint128,
+    min_output: Uint128,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    if env.contract.address != info.sender {
+        return Err(StdError::generic_err(format!(
+            "unauthorized settle; expected caller: {}, caller: {}",
+            env.contract.address, info.sender
+        )));
+    }
+
+    let balance = query_balance(&deps.querier, env.contract.address, asset_info.clone())?;
+    let output = balance.checked_sub(initial_balance)?;
+    let bounty = bounty(deps.as_ref(), output)?;
+    let owner_output = output.checked_sub(bounty)?;
+    if owner_output < min_output {
+        return Err(StdError::generic_err(format!(
+            "assertion failed; order {} output: {}, minimum output: {}",
+            id, owner_output, min_output
+        )));
+    }
+
+    let mut tax = TaxCache::default();
+    let mut response = Response::new()
+        .add_attribute("action", "settle_order")
+        .add_attribute("order_id", id.to_string())
+        .add_attribute("output", owner_output)
+        .add_attribute("bounty", bounty)
+        .add_message(
+            Asset {
+                info: asset_info.clone(),
+                amount: owner_output,
+            }
+            .into_msg(&deps.querier, &mut tax, deps.api.addr_validate(&owner)?)?,
+        );
+    if !bounty.is_zero() {
+        response = response.add_message(
+            Asset {
+                info: asset_info,
+                amount: bounty,
+            }
+            .into_msg(&deps.querier, &mut tax, deps.api.addr_validate(&keeper)?)?,
+        );
+    }
+
+    Ok(response.add_attribute("tax_paid", tax.paid_to_string()))
+}
+
+/// The owner can cancel at any time, anyone can refund an expired order to its owner
+pub fn cancel_order(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> StdResult<Response<TerraMsgWrapper>> {
+    let order = load_order(deps.storage, id)?;
+    if info.sender != order.owner && !order.expiry.is_expired(&env.block) {
+        return Err(StdError::generic_err("unauthorized"));
+    }
+
+    orders().remove(deps.storage, U64Key::from(id))?;
+    remove_escrow(deps.branch(), &order.offer)?;
+
+    let mut tax = TaxCache::default();
+    let refund = order
+        .offer
+        .clone()
+        .into_msg(&deps.querier, &mut tax, order.owner)?;
+    Ok(Response::new()
+        .add_attribute("action", "cancel_order")
+        .add_attribute("order_id", id.to_string())
+        .add_attribute("refund", order.offer.to_string())
+        .add_attribute("tax_paid", tax.paid_to_string())
+        .add_message(refund))
+}
+
+fn order_to_response(order: LimitOrder) -> OrderResponse {
+    OrderResponse {
+        id: order.id,
+        owner: order.owner,
+        steps: order.steps,
+        offer: order.offer,
+        min_output: order.min_output,
+        expiry: order.expiry,
+        trigger: order.trigger,
+    }
+}
+
+pub fn query_order(deps: Deps, id: u64) -> StdResult<OrderResponse> {
+    Ok(order_to_response(load_order(deps.storage, id)?))
+}
+
+pub fn query_orders(
+    deps: Deps,
+    owner: Option<String>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OrdersResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|id| Bound::exclusive(U64Key::from(id)));
+
+    let orders = match owner {
+        Some(owner) => {
+            let owner = deps.api.addr_validate(&owner)?;
+            orders()
+                .idx
+                .owner
+                .prefix(owner.as_bytes().to_vec())
+                .range(deps.storage, start, None, Order::Ascending)
+                .take(limit)
+                .map(|item| item.map(|(_, order)| order_to_response(order)))
+                .collect::<StdResult<Vec<OrderResponse>>>()?
+        }
+        None => orders()
+            .range(deps.storage, start, None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, order)| order_to_response(order)))
+            .collect::<StdResult<Vec<OrderResponse>>>()?,
+    };
+
+    Ok(OrdersResponse { orders })
+}
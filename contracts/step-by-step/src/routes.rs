@@ -0,0 +1,249 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{Deps, Order, StdError, StdResult, Uint128};
+
+use crate::asset::{Asset, AssetInfo, AssetInfoRaw};
+use crate::contract::{assert_operations_not_paused, assert_valid_steps};
+use crate::msg::{RouteResponse, RoutesResponse, StrategyStep};
+use crate::msgs::liquidity_pool::LiquidityPoolSwapMsg;
+use crate::msgs::market::MarketSwapMsg;
+use crate::operations::{StepOperation, StrategyStepOperation};
+use crate::state::{State, PAIRS, STATE};
+use crate::tax::TaxCache;
+
+pub const MAX_ROUTE_HOPS: u32 = 4;
+const DEFAULT_ROUTES_LIMIT: u32 = 3;
+const MAX_ROUTES_LIMIT: u32 = 10;
+/// Hops a route search simulates at most, across all of its depths
+const MAX_ROUTE_SIMULATIONS: usize = 200;
+/// Partial routes reaching the same asset that are extended on the next hop
+const ROUTE_BEAM_WIDTH: usize = 3;
+
+/// A step the route finder can take, from one asset of the graph to another
+struct Edge {
+    from: AssetInfoRaw,
+    to: AssetInfoRaw,
+    step: StrategyStep,
+}
+
+fn step(from: AssetInfo, to: AssetInfo, operation: StrategyStepOperation) -> StrategyStep {
+    StrategyStep {
+        from_asset: from,
+        to_asset: to,
+        operation,
+        max_oracle_deviation: None,
+    }
+}
+
+/// Both directions of every registered pair, and market swaps between every two market denoms
+fn load_edges(deps: Deps, state: &State) -> StdResult<Vec<Edge>> {
+    let mut edges = vec![];
+    for item in PAIRS.range(deps.storage, None, None, Order::Ascending) {
+        let (_, pair) = item?;
+        let asset_infos = [
+            pair.asset_infos[0].to_normal(deps.api)?,
+            pair.asset_infos[1].to_normal(deps.api)?,
+        ];
+        for &(from, to) in &[(0, 1), (1, 0)] {
+            edges.push(Edge {
+                from: pair.asset_infos[from].clone(),
+                to: pair.asset_infos[to].clone(),
+                step: step(
+                    asset_infos[from].clone(),
+                    asset_infos[to].clone(),
+                    StrategyStepOperation::LiquidityPoolSwapOperation {
+                        msg: LiquidityPoolSwapMsg::new(pair.factory_addr.clone()),
+                    },
+                ),
+            });
+        }
+    }
+
+    for from in state.market_denoms.iter() {
+        for to in state.market_denoms.iter().filter(|to| *to != from) {
+            edges.push(Edge {
+                from: AssetInfoRaw::NativeToken {
+                    denom: from.to_string(),
+                },
+                to: AssetInfoRaw::NativeToken {
+                    denom: to.to_string(),
+                },
+                step: step(
+                    AssetInfo::NativeToken {
+                        denom: from.to_string(),
+                    },
+                    AssetInfo::NativeToken {
+                        denom: to.to_string(),
+                    },
+                    StrategyStepOperation::MarketSwapOperation {
+                        msg: MarketSwapMsg {},
+                    },
+                ),
+            });
+        }
+    }
+
+    Ok(edges)
+}
+
+fn asset_key(asset: &AssetInfoRaw) -> Vec<u8> {
+    let tag = match asset {
+        AssetInfoRaw::NativeToken { .. } => b'n',
+        AssetInfoRaw::Token { .. } => b't',
+    };
+    let mut key = vec![tag];
+    key.extend_from_slice(asset.as_bytes());
+    key
+}
+
+/// Indexes of the edges leaving every asset
+fn index_edges(edges: &[Edge]) -> BTreeMap<Vec<u8>, Vec<usize>> {
+    let mut index: BTreeMap<Vec<u8>, Vec<usize>> = BTreeMap::new();
+    for (position, edge) in edges.iter().enumerate() {
+        index
+            .entry(asset_key(&edge.from))
+            .or_default()
+            .push(position);
+    }
+    index
+}
+
+/// A route being searched, `amount` is the simulated output of its last edge
+struct PartialRoute {
+    asset: AssetInfoRaw,
+    path: Vec<usize>,
+    amount: Uint128,
+}
+
+impl PartialRoute {
+    fn visits(&self, edges: &[Edge], offer: &AssetInfoRaw, asset: &AssetInfoRaw) -> bool {
+        offer.equal(asset) || self.path.iter().any(|&index| edges[index].to.equal(asset))
+    }
+}
+
+/// Searches the routes from `offer` to `ask` of at most `max_hops` edges not going through an asset twice,
+/// routes from an asset to itself are cycles ending on their first asset.
+/// Every hop is simulated as it is taken, only the `ROUTE_BEAM_WIDTH` best partial routes reaching
+/// an asset are extended and the search stops after `MAX_ROUTE_SIMULATIONS` simulated hops.
+fn search(
+    deps: Deps,
+    edges: &[Edge],
+    offer: &AssetInfoRaw,
+    ask: &AssetInfoRaw,
+    max_hops: u32,
+    amount: Uint128,
+) -> Vec<RouteResponse> {
+    let index = index_edges(edges);
+    let mut tax = TaxCache::default();
+    let mut simulations = 0;
+    let mut routes = vec![];
+    let mut frontier = vec![PartialRoute {
+        asset: offer.clone(),
+        path: vec![],
+        amount,
+    }];
+
+    for hop in 1..=max_hops {
+        let mut next: Vec<PartialRoute> = vec![];
+        for partial in frontier.iter() {
+            let leaving = index
+                .get(&asset_key(&partial.asset))
+                .map_or(&[][..], |leaving| leaving.as_slice());
+            for &position in leaving {
+                let edge = &edges[position];
+                let arrives = edge.to.equal(ask);
+                if !arrives && (hop == max_hops || partial.visits(edges, offer, &edge.to)) {
+                    continue;
+                }
+
+                if simulations == MAX_ROUTE_SIMULATIONS {
+                    return routes;
+                }
+                simulations += 1;
+
+                let return_amount = match edge.step.operation.simulate(
+                    deps,
+                    &mut tax,
+                    &Asset {
+                        info: edge.step.get_from_asset(),
+                        amount: partial.amount,
+                    },
+                    &edge.step.get_to_asset(),
+                    arrives,
+                ) {
+                    Ok(return_amount) if !return_amount.is_zero() => return_amount,
+                    _ => continue,
+                };
+
+                let mut path = partial.path.clone();
+                path.push(position);
+                if arrives {
+                    routes.push(RouteResponse {
+                        steps: path
+                            .iter()
+                            .map(|&index| edges[index].step.clone())
+                            .collect(),
+                        return_amount,
+                    });
+                } else {
+                    next.push(PartialRoute {
+                        asset: edge.to.clone(),
+                        path,
+                        amount: return_amount,
+                    });
+                }
+            }
+        }
+
+        // amounts of different assets can not be compared, the beam is kept per asset
+        next.sort_by_key(|partial| Reverse(partial.amount));
+        frontier = vec![];
+        for partial in next {
+            let reaching = frontier
+                .iter()
+                .filter(|kept| kept.asset.equal(&partial.asset))
+                .count();
+            if reaching < ROUTE_BEAM_WIDTH {
+                frontier.push(partial);
+            }
+        }
+    }
+
+    routes
+}
+
+/// Routes from `offer_asset` to `ask_asset` ranked by their simulated output,
+/// routes that can not be executed or simulated are left out.
+pub fn find_routes(
+    deps: Deps,
+    offer_asset: AssetInfo,
+    ask_asset: AssetInfo,
+    max_hops: u32,
+    amount: Uint128,
+    limit: Option<u32>,
+) -> StdResult<RoutesResponse> {
+    if max_hops == 0 || max_hops > MAX_ROUTE_HOPS {
+        return Err(StdError::generic_err(format!(
+            "invalid max hops: {}, should be between 1 and {}",
+            max_hops, MAX_ROUTE_HOPS
+        )));
+    }
+
+    let state = STATE.load(deps.storage)?;
+    let limit = limit.unwrap_or(DEFAULT_ROUTES_LIMIT).min(MAX_ROUTES_LIMIT) as usize;
+    let offer = offer_asset.to_raw(deps.api)?;
+    let ask = ask_asset.to_raw(deps.api)?;
+    let mut edges = load_edges(deps, &state)?;
+    edges.retain(|edge| {
+        let steps = std::slice::from_ref(&edge.step);
+        assert_valid_steps(deps, steps).is_ok()
+            && assert_operations_not_paused(&state, steps).is_ok()
+    });
+
+    let mut routes = search(deps, &edges, &offer, &ask, max_hops, amount);
+    routes.sort_by_key(|route| Reverse(route.return_amount));
+    routes.truncate(limit);
+
+    Ok(RoutesResponse { routes })
+}
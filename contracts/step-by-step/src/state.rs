@@ -1,13 +1,228 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::Addr;
-use cw_storage_plus::Item;
+use cosmwasm_std::{Addr, Decimal, Empty, Uint128};
+use cw20::Expiration;
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex, U64Key};
+
+use crate::asset::{Asset, AssetInfoRaw};
+use crate::msg::{AllowlistKind, ExecutorAccess, OrderTrigger, ReferencePrice, StrategyStep};
+use crate::tax::TaxCache;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct State {
     pub owner: Addr,
     pub comission: i16,
+    /// account allowed to pause and unpause the router besides the owner
+    pub guardian: Option<Addr>,
+    pub paused: bool,
+    /// operation kinds paused while the rest of the router keeps working
+    pub paused_operations: Vec<String>,
+    /// when set, the allowlists are not enforced
+    pub permissionless: bool,
+    pub executor_access: ExecutorAccess,
+    /// max spread of the pool swaps that set neither their own nor a strategy one
+    pub default_max_spread: Option<Decimal>,
+    /// denoms the route finder can swap through the market
+    pub market_denoms: Vec<String>,
+    /// share of a filled order output paid to the keeper executing it
+    pub order_bounty: Decimal,
+    /// share of a DCA run output paid to the keeper triggering it
+    pub dca_bounty: Decimal,
+    /// share of an arbitrage profit paid to the account triggering it
+    pub arbitrage_bounty: Decimal,
+    /// contract flash loan strategies borrow from
+    pub flash_lender: Option<Addr>,
+    /// share of the principal charged on the flash loans the router offers
+    pub flash_loan_fee: Decimal,
+}
+
+impl State {
+    pub fn can_pause(&self, sender: &Addr) -> bool {
+        *sender == self.owner || self.guardian.as_ref() == Some(sender)
+    }
 }
 
 pub const STATE: Item<State> = Item::new("state");
+
+/// A validated route stored by `RegisterStrategy`.
+/// Strategies without an owner are global and can be executed by anyone,
+/// user-scoped strategies can only be executed by the account that registered them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SavedStrategy {
+    pub id: u64,
+    pub name: String,
+    pub owner: Option<Addr>,
+    pub steps: Vec<StrategyStep>,
+    pub max_spread: Option<Decimal>,
+}
+
+/// Incremented on every strategy execution so hops can be correlated in the tx logs
+pub const EXECUTION_COUNT: Item<u64> = Item::new("execution_count");
+
+/// Context of the strategy being executed, shared between its steps
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Execution {
+    pub id: u64,
+    /// amount spent by the first step, sent by the caller or taken from the inventory
+    pub offer_amount: Uint128,
+    /// balance of the asset received by the previous step, taken before it was swapped
+    pub previous_ask_balance: Uint128,
+    pub tax: TaxCache,
+    /// max spread applied to the pool swaps that do not set one
+    pub max_spread: Option<Decimal>,
+}
+
+pub const EXECUTION: Item<Execution> = Item::new("execution");
+
+pub const STRATEGY_COUNT: Item<u64> = Item::new("strategy_count");
+pub const STRATEGIES: Map<U64Key, SavedStrategy> = Map::new("strategies");
+
+pub const ALLOWED_FACTORIES: Map<&Addr, Empty> = Map::new("allowed_factories");
+pub const ALLOWED_PAIRS: Map<&Addr, Empty> = Map::new("allowed_pairs");
+pub const ALLOWED_TOKENS: Map<&Addr, Empty> = Map::new("allowed_tokens");
+
+/// Accounts allowed to execute strategies depending on the `ExecutorAccess` mode
+pub const EXECUTORS: Map<&Addr, Empty> = Map::new("executors");
+
+/// Prices cw20 tokens are checked against when a step asserts its oracle rate
+pub const REFERENCE_PRICES: Map<&Addr, ReferencePrice> = Map::new("reference_prices");
+
+/// Pair searched by the route finder, keyed by its contract address
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RegisteredPair {
+    pub factory_addr: Addr,
+    pub asset_infos: [AssetInfoRaw; 2],
+}
+
+pub const PAIRS: Map<&Addr, RegisteredPair> = Map::new("pairs");
+
+/// Limit order placed by `owner`, its offer is held by the router until it is filled or cancelled
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LimitOrder {
+    pub id: u64,
+    pub owner: Addr,
+    pub steps: Vec<StrategyStep>,
+    pub offer: Asset,
+    /// minimum the owner receives, after the keeper bounty
+    pub min_output: Uint128,
+    pub expiry: Expiration,
+    /// conditional orders are filled once their trigger is met
+    pub trigger: Option<OrderTrigger>,
+}
+
+pub struct OrderIndexes<'a> {
+    pub owner: MultiIndex<'a, (Vec<u8>, Vec<u8>), LimitOrder>,
+}
+
+impl<'a> IndexList<LimitOrder> for OrderIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<LimitOrder>> + '_> {
+        let v: Vec<&dyn Index<LimitOrder>> = vec![&self.owner];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn orders<'a>() -> IndexedMap<'a, U64Key, LimitOrder, OrderIndexes<'a>> {
+    let indexes = OrderIndexes {
+        owner: MultiIndex::new(
+            |order, pk| (order.owner.as_bytes().to_vec(), pk),
+            "orders",
+            "orders__owner",
+        ),
+    };
+    IndexedMap::new("orders", indexes)
+}
+
+pub const ORDER_COUNT: Item<u64> = Item::new("order_count");
+
+/// Recurring strategy spending `amount_per_run` of its escrowed budget every `interval` blocks
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Dca {
+    pub id: u64,
+    pub owner: Addr,
+    pub steps: Vec<StrategyStep>,
+    pub amount_per_run: Uint128,
+    /// budget left, refunded to the owner once lower than `amount_per_run`
+    pub remaining: Asset,
+    pub interval: u64,
+    /// share of the simulated output a run may miss
+    pub tolerance: Decimal,
+    /// first height the next run can be triggered at
+    pub next_run_height: u64,
+    pub runs: u64,
+}
+
+pub struct DcaIndexes<'a> {
+    pub owner: MultiIndex<'a, (Vec<u8>, Vec<u8>), Dca>,
+}
+
+impl<'a> IndexList<Dca> for DcaIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Dca>> + '_> {
+        let v: Vec<&dyn Index<Dca>> = vec![&self.owner];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn dcas<'a>() -> IndexedMap<'a, U64Key, Dca, DcaIndexes<'a>> {
+    let indexes = DcaIndexes {
+        owner: MultiIndex::new(
+            |dca, pk| (dca.owner.as_bytes().to_vec(), pk),
+            "dcas",
+            "dcas__owner",
+        ),
+    };
+    IndexedMap::new("dcas", indexes)
+}
+
+pub const DCA_COUNT: Item<u64> = Item::new("dca_count");
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct DcaRun {
+    pub run: u64,
+    pub height: u64,
+    pub offer_amount: Uint128,
+    pub minimum_receive: Uint128,
+    /// set once the run output, net of the keeper bounty, reached the owner
+    pub return_amount: Option<Uint128>,
+}
+
+/// Runs of every recurring strategy, keyed by the strategy id and the run number
+pub const DCA_RUNS: Map<(U64Key, U64Key), DcaRun> = Map::new("dca_runs");
+
+/// Flash loan taken for the strategy being executed, repaid before the strategy is finalized
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FlashLoan {
+    pub lender: Addr,
+    pub asset: Asset,
+    pub fee: Uint128,
+    pub steps: Vec<StrategyStep>,
+    /// router balance of the borrowed asset before the loan
+    pub initial_balance: Uint128,
+    pub minimum_profit: Uint128,
+}
+
+pub const FLASH_LOAN: Item<FlashLoan> = Item::new("flash_loan");
+
+/// Circular strategy registered by the owner, anyone can run it on the router inventory
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Arbitrage {
+    pub id: u64,
+    pub steps: Vec<StrategyStep>,
+    /// inventory growth required from every run, after the bounty
+    pub minimum_profit: Uint128,
+}
+
+pub const ARBITRAGE_COUNT: Item<u64> = Item::new("arbitrage_count");
+pub const ARBITRAGES: Map<U64Key, Arbitrage> = Map::new("arbitrages");
+
+/// Amount of each asset held for open orders and recurring strategies, keyed by the raw asset info,
+/// it is not part of the router inventory
+pub const ESCROW: Map<&[u8], Uint128> = Map::new("escrow");
+
+pub fn allowlist<'a>(kind: &AllowlistKind) -> Map<'a, &'a Addr, Empty> {
+    match kind {
+        AllowlistKind::Factory => ALLOWED_FACTORIES,
+        AllowlistKind::Pair => ALLOWED_PAIRS,
+        AllowlistKind::Token => ALLOWED_TOKENS,
+    }
+}
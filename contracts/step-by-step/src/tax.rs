@@ -0,0 +1,258 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{Coin, Decimal, QuerierWrapper, StdError, StdResult, Uint128};
+use terra_cosmwasm::TerraQuerier;
+
+use crate::asset::{Asset, AssetInfo};
+
+pub(crate) static DECIMAL_FRACTION: Uint128 = Uint128::new(1_000_000_000_000_000_000u128);
+
+/// Stability tax charged on top of a native transfer of `amount`, so that
+/// `amount - tax` is what can be sent when `amount` is the whole balance.
+pub fn compute_tax(amount: Uint128, rate: Decimal, cap: Uint128) -> StdResult<Uint128> {
+    Ok(std::cmp::min(
+        amount.checked_sub(amount.multiply_ratio(
+            DECIMAL_FRACTION,
+            (DECIMAL_FRACTION * rate).checked_add(DECIMAL_FRACTION)?,
+        ))?,
+        cap,
+    ))
+}
+
+fn is_taxed(denom: &str) -> bool {
+    denom != "uluna"
+}
+
+/// Tax rate and caps queried at most once per strategy execution,
+/// along with the total tax paid by the native transfers the router emitted.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct TaxCache {
+    pub rate: Option<Decimal>,
+    pub caps: Vec<Coin>,
+    pub paid: Vec<Coin>,
+}
+
+impl TaxCache {
+    fn rate(&mut self, querier: &QuerierWrapper) -> StdResult<Decimal> {
+        match self.rate {
+            Some(rate) => Ok(rate),
+            None => {
+                let rate = TerraQuerier::new(querier).query_tax_rate()?.rate;
+                self.rate = Some(rate);
+                Ok(rate)
+            }
+        }
+    }
+
+    fn cap(&mut self, querier: &QuerierWrapper, denom: &str) -> StdResult<Uint128> {
+        if let Some(cap) = self.caps.iter().find(|cap| cap.denom == denom) {
+            return Ok(cap.amount);
+        }
+
+        let cap = TerraQuerier::new(querier)
+            .query_tax_cap(denom.to_string())?
+            .cap;
+        self.caps.push(Coin {
+            denom: denom.to_string(),
+            amount: cap,
+        });
+        Ok(cap)
+    }
+
+    pub fn compute_tax(&mut self, querier: &QuerierWrapper, asset: &Asset) -> StdResult<Uint128> {
+        match &asset.info {
+            AssetInfo::NativeToken { denom } if is_taxed(denom) => {
+                let rate = self.rate(querier)?;
+                let cap = self.cap(querier, denom)?;
+                compute_tax(asset.amount, rate, cap)
+            }
+            _ => Ok(Uint128::zero()),
+        }
+    }
+
+    /// Deducts the tax from a native asset about to be transferred and records it as paid,
+    /// returns the amount left to send.
+    pub fn deduct_tax(&mut self, querier: &QuerierWrapper, asset: &Asset) -> StdResult<Uint128> {
+        let tax = self.compute_tax(querier, asset)?;
+        if let AssetInfo::NativeToken { denom } = &asset.info {
+            if !tax.is_zero() {
+                match self.paid.iter_mut().find(|paid| paid.denom == *denom) {
+                    Some(paid) => paid.amount += tax,
+                    None => self.paid.push(Coin {
+                        denom: denom.to_string(),
+                        amount: tax,
+                    }),
+                }
+            }
+        }
+
+        Ok(asset.amount.checked_sub(tax)?)
+    }
+
+    /// Amount to transfer for the recipient of a native asset to receive all of it
+    /// once `deduct_tax` took its share.
+    pub fn add_tax(&mut self, querier: &QuerierWrapper, asset: &Asset) -> StdResult<Uint128> {
+        match &asset.info {
+            AssetInfo::NativeToken { denom } if is_taxed(denom) => {
+                let rate = self.rate(querier)?;
+                if rate > Decimal::one() {
+                    return Err(StdError::generic_err(format!("invalid tax rate: {}", rate)));
+                }
+                // amount * rate rounded up, the tax deducted from the total is then never higher
+                let tax = asset
+                    .amount
+                    .checked_sub(asset.amount * (Decimal::one() - rate))?;
+                let cap = self.cap(querier, denom)?;
+                Ok(asset.amount.checked_add(std::cmp::min(tax, cap))?)
+            }
+            _ => Ok(asset.amount),
+        }
+    }
+
+    /// Tax paid so far, formatted for the response attributes
+    pub fn paid_to_string(&self) -> String {
+        self.paid
+            .iter()
+            .map(|coin| coin.to_string())
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::mock_dependencies;
+    use proptest::prelude::*;
+    use std::str::FromStr;
+
+    fn uusd(amount: u128) -> Asset {
+        Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uusd".to_string(),
+            },
+            amount: Uint128::new(amount),
+        }
+    }
+
+    #[test]
+    fn cached_tax_is_accumulated() {
+        let deps = mock_dependencies(&[]);
+        let querier = QuerierWrapper::new(&deps.querier);
+
+        // cached values are used without querying the chain
+        let mut tax = TaxCache {
+            rate: Some(Decimal::from_str("0.01").unwrap()),
+            caps: vec![Coin::new(5, "uusd")],
+            paid: vec![],
+        };
+
+        assert_eq!(
+            Uint128::new(1),
+            tax.compute_tax(&querier, &uusd(101)).unwrap()
+        );
+        assert_eq!(
+            Uint128::new(100),
+            tax.deduct_tax(&querier, &uusd(101)).unwrap()
+        );
+        // capped
+        assert_eq!(
+            Uint128::new(995),
+            tax.deduct_tax(&querier, &uusd(1000)).unwrap()
+        );
+        assert_eq!(vec![Coin::new(6, "uusd")], tax.paid);
+
+        let uluna = Asset {
+            info: AssetInfo::NativeToken {
+                denom: "uluna".to_string(),
+            },
+            amount: Uint128::new(1000),
+        };
+        assert_eq!(
+            Uint128::new(1000),
+            tax.deduct_tax(&querier, &uluna).unwrap()
+        );
+        assert_eq!(vec![Coin::new(6, "uusd")], tax.paid);
+    }
+
+    #[test]
+    fn tax_is_queried_once() {
+        let mut deps = crate::testing::mock_dependencies(&[]);
+        deps.querier
+            .with_tax(Decimal::from_str("0.01").unwrap(), &[Coin::new(5, "uusd")]);
+        let querier = QuerierWrapper::new(&deps.querier);
+
+        let mut tax = TaxCache::default();
+        assert_eq!(
+            Uint128::new(1),
+            tax.compute_tax(&querier, &uusd(101)).unwrap()
+        );
+        assert_eq!(Some(Decimal::from_str("0.01").unwrap()), tax.rate);
+        assert_eq!(vec![Coin::new(5, "uusd")], tax.caps);
+        assert_eq!(
+            compute_tax(
+                Uint128::new(1000),
+                Decimal::from_str("0.01").unwrap(),
+                Uint128::new(5)
+            )
+            .unwrap(),
+            tax.compute_tax(&querier, &uusd(1000)).unwrap()
+        );
+    }
+
+    fn tax_rate() -> impl Strategy<Value = Decimal> {
+        prop_oneof![
+            (0u64..=1000).prop_map(Decimal::permille),
+            any::<u64>().prop_map(|rate| Decimal::from_ratio(rate, 1000u128)),
+            Just(Decimal::MAX),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn tax_is_bounded(amount in any::<u128>(), rate in tax_rate(), cap in any::<u128>()) {
+            let (amount, cap) = (Uint128::new(amount), Uint128::new(cap));
+            match compute_tax(amount, rate, cap) {
+                Ok(tax) => {
+                    prop_assert!(tax <= amount);
+                    prop_assert!(tax <= cap);
+                }
+                // only rates far above anything the treasury sets overflow
+                Err(_) => prop_assert!(rate > Decimal::from_ratio(u64::MAX, 1000u128)),
+            }
+        }
+
+        #[test]
+        fn deducted_and_tax_add_up(amount in any::<u128>(), permille in 0u64..=1000, cap in any::<u128>()) {
+            let deps = mock_dependencies(&[]);
+            let querier = QuerierWrapper::new(&deps.querier);
+            let mut tax = TaxCache {
+                rate: Some(Decimal::permille(permille)),
+                caps: vec![Coin::new(cap, "uusd")],
+                paid: vec![],
+            };
+
+            let computed = tax.compute_tax(&querier, &uusd(amount)).unwrap();
+            let deducted = tax.deduct_tax(&querier, &uusd(amount)).unwrap();
+            prop_assert_eq!(Uint128::new(amount), deducted + computed);
+        }
+
+        #[test]
+        fn added_tax_covers_the_deducted_one(amount in 0u128..u64::MAX as u128, permille in 0u64..=1000, cap in any::<u64>()) {
+            let deps = mock_dependencies(&[]);
+            let querier = QuerierWrapper::new(&deps.querier);
+            let mut tax = TaxCache {
+                rate: Some(Decimal::permille(permille)),
+                caps: vec![Coin::new(cap as u128, "uusd")],
+                paid: vec![],
+            };
+
+            let total = tax.add_tax(&querier, &uusd(amount)).unwrap();
+            let deducted = tax.deduct_tax(&querier, &uusd(total.u128())).unwrap();
+            prop_assert!(deducted >= Uint128::new(amount));
+            // never more than the rounding of the tax above what the recipient needs
+            prop_assert!(deducted <= Uint128::new(amount) + Uint128::new(1));
+        }
+    }
+}
@@ -0,0 +1,725 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::testing::{mock_env, MockApi, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+    from_binary, to_binary, Addr, Attribute, BankMsg, BlockInfo, Coin, CosmosMsg, Decimal, Deps,
+    DepsMut, Env, MessageInfo, Order, Pair, QuerierWrapper, ReplyOn, StdError, StdResult, Storage,
+    Uint128, WasmMsg,
+};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use terra_cosmwasm::{TerraMsg, TerraMsgWrapper};
+use terraswap::pair::Cw20HookMsg as PairCw20HookMsg;
+use terraswap::pair::ExecuteMsg as PairExecuteMsg;
+
+use crate::asset::{Asset, AssetInfo};
+use crate::contract::{execute, instantiate, query};
+use crate::lender::FlashLenderExecuteMsg;
+use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+use crate::tax::compute_tax;
+use crate::testing::querier::{
+    compute_swap, from_terraswap_info, initial_share, native, MockPair, WasmMockQuerier,
+    DECIMAL_FRACTION,
+};
+
+/// Router storage, cloned to roll back failed transactions
+#[derive(Clone, Default)]
+struct ChainStorage(BTreeMap<Vec<u8>, Vec<u8>>);
+
+impl Storage for ChainStorage {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key).cloned()
+    }
+
+    fn range<'a>(
+        &'a self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = Pair> + 'a> {
+        if let (Some(start), Some(end)) = (start, end) {
+            if start > end {
+                return Box::new(std::iter::empty());
+            }
+        }
+
+        let start = start.map_or(Bound::Unbounded, |start| Bound::Included(start.to_vec()));
+        let end = end.map_or(Bound::Unbounded, |end| Bound::Excluded(end.to_vec()));
+        let iter = self
+            .0
+            .range((start, end))
+            .map(|(key, value)| (key.clone(), value.clone()));
+        match order {
+            Order::Ascending => Box::new(iter),
+            Order::Descending => Box::new(iter.rev()),
+        }
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.0.insert(key.to_vec(), value.to_vec());
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.0.remove(key);
+    }
+}
+
+fn assert_max_spread(
+    belief_price: Option<Decimal>,
+    max_spread: Option<Decimal>,
+    offer_amount: Uint128,
+    return_amount: Uint128,
+    spread_amount: Uint128,
+) -> StdResult<()> {
+    let exceeded = match (max_spread, belief_price) {
+        (Some(max_spread), Some(belief_price)) => {
+            let expected_return =
+                offer_amount.multiply_ratio(DECIMAL_FRACTION, DECIMAL_FRACTION * belief_price);
+            return_amount < expected_return
+                && Decimal::from_ratio(expected_return - return_amount, expected_return)
+                    > max_spread
+        }
+        (Some(max_spread), None) => {
+            Decimal::from_ratio(spread_amount, return_amount + spread_amount) > max_spread
+        }
+        _ => false,
+    };
+
+    if exceeded {
+        return Err(StdError::generic_err("Operation exceeds max spread limit"));
+    }
+
+    Ok(())
+}
+
+/// Message handled by the mock flash borrowers, sent as the callback of a router flash loan
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MockBorrowerMsg {
+    /// sends `asset` from the borrower back to the lender
+    Repay { asset: Asset },
+}
+
+/// Runs the router against mocked Terraswap factories and pairs, flash lenders and borrowers,
+/// cw20 tokens and the Terra market and treasury modules.
+/// Messages are executed depth first and a failed execution rolls back every change.
+pub struct MockChain {
+    router: String,
+    api: MockApi,
+    storage: ChainStorage,
+    querier: WasmMockQuerier,
+    block: BlockInfo,
+    borrowers: Vec<String>,
+}
+
+impl Default for MockChain {
+    fn default() -> Self {
+        MockChain {
+            router: MOCK_CONTRACT_ADDR.to_string(),
+            api: MockApi::default(),
+            storage: ChainStorage::default(),
+            querier: WasmMockQuerier::default(),
+            block: mock_env().block,
+            borrowers: vec![],
+        }
+    }
+}
+
+impl MockChain {
+    pub fn new() -> Self {
+        MockChain::default()
+    }
+
+    pub fn router(&self) -> Addr {
+        Addr::unchecked(&self.router)
+    }
+
+    pub fn balance(&self, holder: &str, info: &AssetInfo) -> Uint128 {
+        self.querier.balance(info, holder)
+    }
+
+    pub fn set_balance(&mut self, holder: &str, info: &AssetInfo, amount: Uint128) {
+        self.querier.set_balance(info, holder, amount);
+    }
+
+    /// Moves the chain `blocks` blocks forward, 5 seconds each
+    pub fn advance_blocks(&mut self, blocks: u64) {
+        self.block.height += blocks;
+        self.block.time = self.block.time.plus_seconds(5 * blocks);
+    }
+
+    pub fn block(&self) -> BlockInfo {
+        self.block.clone()
+    }
+
+    fn env(&self) -> Env {
+        let mut env = mock_env();
+        env.block = self.block.clone();
+        env
+    }
+
+    /// Registers a contract borrowing from the router, it pays with its own balances
+    pub fn with_flash_borrower(&mut self, contract_addr: &str) {
+        self.borrowers.push(contract_addr.to_string());
+    }
+
+    /// Access to the balances, pairs and prices the chain runs against
+    pub fn querier(&mut self) -> &mut WasmMockQuerier {
+        &mut self.querier
+    }
+
+    pub fn instantiate(&mut self, sender: &str, msg: InstantiateMsg) -> StdResult<()> {
+        let env = self.env();
+        let deps = DepsMut {
+            storage: &mut self.storage,
+            api: &self.api,
+            querier: QuerierWrapper::new(&self.querier),
+        };
+        instantiate(
+            deps,
+            env,
+            MessageInfo {
+                sender: Addr::unchecked(sender),
+                funds: vec![],
+            },
+            msg,
+        )
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+        Ok(())
+    }
+
+    /// Executes `msg` on `contract` as `sender`, returns the attributes emitted by every message
+    pub fn execute<T: Serialize>(
+        &mut self,
+        sender: &str,
+        contract: &str,
+        msg: &T,
+        funds: &[Coin],
+    ) -> StdResult<Vec<Attribute>> {
+        let storage = self.storage.clone();
+        let querier = self.querier.clone();
+
+        let res = self.dispatch(
+            sender,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: contract.to_string(),
+                msg: to_binary(msg)?,
+                funds: funds.to_vec(),
+            }),
+        );
+        if res.is_err() {
+            self.storage = storage;
+            self.querier = querier;
+        }
+
+        res
+    }
+
+    pub fn query<T: DeserializeOwned>(&self, msg: QueryMsg) -> StdResult<T> {
+        let deps = Deps {
+            storage: &self.storage,
+            api: &self.api,
+            querier: QuerierWrapper::new(&self.querier),
+        };
+        from_binary(&query(deps, self.env(), msg)?)
+    }
+
+    fn dispatch(
+        &mut self,
+        sender: &str,
+        msg: CosmosMsg<TerraMsgWrapper>,
+    ) -> StdResult<Vec<Attribute>> {
+        match msg {
+            CosmosMsg::Bank(BankMsg::Send { to_address, amount }) => {
+                for coin in amount.iter() {
+                    self.querier.send_native(sender, &to_address, coin)?;
+                }
+                Ok(vec![])
+            }
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr,
+                msg,
+                funds,
+            }) => {
+                for coin in funds.iter() {
+                    self.querier.send_native(sender, &contract_addr, coin)?;
+                }
+
+                if contract_addr == self.router {
+                    self.execute_router(sender, from_binary(&msg)?, funds)
+                } else if let Some(pair) = self.querier.pair(&contract_addr) {
+                    self.execute_pair(sender, pair, from_binary(&msg)?, funds)
+                } else if let Some(fee_rate) = self.querier.flash_fee_rate(&contract_addr) {
+                    self.execute_lender(sender, &contract_addr, fee_rate, from_binary(&msg)?)
+                } else if self.borrowers.contains(&contract_addr) {
+                    self.execute_borrower(sender, &contract_addr, from_binary(&msg)?)
+                } else {
+                    self.execute_token(sender, &contract_addr, from_binary(&msg)?)
+                }
+            }
+            CosmosMsg::Custom(TerraMsgWrapper { msg_data, .. }) => match msg_data {
+                TerraMsg::Swap {
+                    offer_coin,
+                    ask_denom,
+                } => {
+                    let return_amount = self.querier.market_swap(&offer_coin, &ask_denom)?;
+                    self.querier
+                        .sub(&native(&offer_coin.denom), sender, offer_coin.amount)?;
+                    self.querier
+                        .add(&native(&ask_denom), sender, return_amount)?;
+                    Ok(vec![])
+                }
+                TerraMsg::SwapSend {
+                    to_address,
+                    offer_coin,
+                    ask_denom,
+                } => {
+                    let return_amount = self.querier.market_swap(&offer_coin, &ask_denom)?;
+                    let tax = self.querier.tax(&offer_coin.denom, offer_coin.amount);
+                    self.querier.sub(
+                        &native(&offer_coin.denom),
+                        sender,
+                        offer_coin.amount + tax,
+                    )?;
+                    self.querier
+                        .add(&native(&ask_denom), &to_address, return_amount)?;
+                    Ok(vec![])
+                }
+            },
+            _ => Err(StdError::generic_err("unsupported message")),
+        }
+    }
+
+    fn execute_router(
+        &mut self,
+        sender: &str,
+        msg: ExecuteMsg,
+        funds: Vec<Coin>,
+    ) -> StdResult<Vec<Attribute>> {
+        let env = self.env();
+        let deps = DepsMut {
+            storage: &mut self.storage,
+            api: &self.api,
+            querier: QuerierWrapper::new(&self.querier),
+        };
+        let res = execute(
+            deps,
+            env,
+            MessageInfo {
+                sender: Addr::unchecked(sender),
+                funds,
+            },
+            msg,
+        )?;
+
+        let router = self.router.clone();
+        let mut attributes = res.attributes;
+        for sub_msg in res.messages {
+            if sub_msg.reply_on != ReplyOn::Never {
+                return Err(StdError::generic_err("replies are not supported"));
+            }
+            attributes.extend(self.dispatch(&router, sub_msg.msg)?);
+        }
+
+        Ok(attributes)
+    }
+
+    fn execute_token(
+        &mut self,
+        sender: &str,
+        token: &str,
+        msg: Cw20ExecuteMsg,
+    ) -> StdResult<Vec<Attribute>> {
+        let info = AssetInfo::Token {
+            contract_addr: token.to_string(),
+        };
+        match msg {
+            Cw20ExecuteMsg::Transfer { recipient, amount } => {
+                self.querier.transfer(&info, sender, &recipient, amount)?;
+                Ok(vec![])
+            }
+            Cw20ExecuteMsg::IncreaseAllowance {
+                spender, amount, ..
+            } => {
+                self.querier
+                    .increase_allowance(token, sender, &spender, amount);
+                Ok(vec![])
+            }
+            Cw20ExecuteMsg::Send {
+                contract,
+                amount,
+                msg,
+            } => {
+                self.querier.transfer(&info, sender, &contract, amount)?;
+
+                if contract == self.router {
+                    self.execute_router(
+                        token,
+                        ExecuteMsg::Receive(Cw20ReceiveMsg {
+                            sender: sender.to_string(),
+                            amount,
+                            msg,
+                        }),
+                        vec![],
+                    )
+                } else if let Some(pair) = self.querier.pair(&contract) {
+                    match from_binary(&msg)? {
+                        PairCw20HookMsg::Swap {
+                            belief_price,
+                            max_spread,
+                            to,
+                        } => self.swap(
+                            sender,
+                            pair,
+                            Asset { info, amount },
+                            belief_price,
+                            max_spread,
+                            to,
+                        ),
+                        PairCw20HookMsg::WithdrawLiquidity {} => {
+                            if token != pair.liquidity_token {
+                                return Err(StdError::generic_err("unauthorized"));
+                            }
+                            self.withdraw_liquidity(sender, pair, amount)
+                        }
+                    }
+                } else if self.borrowers.contains(&contract) {
+                    self.execute_borrower(sender, &contract, from_binary(&msg)?)
+                } else {
+                    Err(StdError::generic_err(format!(
+                        "{} can not receive tokens",
+                        contract
+                    )))
+                }
+            }
+            _ => Err(StdError::generic_err("unsupported token message")),
+        }
+    }
+
+    /// Lends the way a flash lender does, the loan must be back with its fee once `msg` returns
+    fn execute_lender(
+        &mut self,
+        sender: &str,
+        lender: &str,
+        fee_rate: Decimal,
+        msg: FlashLenderExecuteMsg,
+    ) -> StdResult<Vec<Attribute>> {
+        match msg {
+            FlashLenderExecuteMsg::FlashLoan { asset, msg } => {
+                match &asset.info {
+                    AssetInfo::NativeToken { denom } => self.querier.send_native(
+                        lender,
+                        sender,
+                        &Coin {
+                            denom: denom.to_string(),
+                            amount: asset.amount,
+                        },
+                    )?,
+                    AssetInfo::Token { .. } => {
+                        self.querier
+                            .transfer(&asset.info, lender, sender, asset.amount)?
+                    }
+                }
+                let balance = self.querier.balance(&asset.info, lender);
+
+                let mut attributes = vec![
+                    Attribute::new("action", "flash_loan"),
+                    Attribute::new("borrower", sender),
+                ];
+                attributes.extend(self.dispatch(
+                    lender,
+                    CosmosMsg::Wasm(WasmMsg::Execute {
+                        contract_addr: sender.to_string(),
+                        msg,
+                        funds: vec![],
+                    }),
+                )?);
+
+                let fee = asset.amount * fee_rate;
+                if self.querier.balance(&asset.info, lender) < balance + asset.amount + fee {
+                    return Err(StdError::generic_err("flash loan not repaid"));
+                }
+
+                Ok(attributes)
+            }
+        }
+    }
+
+    fn execute_borrower(
+        &mut self,
+        lender: &str,
+        borrower: &str,
+        msg: MockBorrowerMsg,
+    ) -> StdResult<Vec<Attribute>> {
+        match msg {
+            MockBorrowerMsg::Repay { asset } => {
+                match &asset.info {
+                    AssetInfo::NativeToken { denom } => self.querier.send_native(
+                        borrower,
+                        lender,
+                        &Coin {
+                            denom: denom.to_string(),
+                            amount: asset.amount,
+                        },
+                    )?,
+                    AssetInfo::Token { .. } => {
+                        self.querier
+                            .transfer(&asset.info, borrower, lender, asset.amount)?
+                    }
+                }
+
+                Ok(vec![
+                    Attribute::new("action", "repay"),
+                    Attribute::new("repaid", asset.to_string()),
+                ])
+            }
+        }
+    }
+
+    fn execute_pair(
+        &mut self,
+        sender: &str,
+        pair: MockPair,
+        msg: PairExecuteMsg,
+        funds: Vec<Coin>,
+    ) -> StdResult<Vec<Attribute>> {
+        match msg {
+            PairExecuteMsg::Swap {
+                offer_asset,
+                belief_price,
+                max_spread,
+                to,
+            } => {
+                let offer_asset = Asset {
+                    info: from_terraswap_info(&offer_asset.info),
+                    amount: offer_asset.amount,
+                };
+                let sent = match &offer_asset.info {
+                    AssetInfo::NativeToken { denom } => funds
+                        .iter()
+                        .find(|coin| coin.denom == *denom)
+                        .map(|coin| coin.amount)
+                        .unwrap_or_default(),
+                    AssetInfo::Token { .. } => Uint128::zero(),
+                };
+                if sent != offer_asset.amount {
+                    return Err(StdError::generic_err(
+                        "Native token balance mismatch between the argument and the transferred",
+                    ));
+                }
+
+                self.swap(sender, pair, offer_asset, belief_price, max_spread, to)
+            }
+            PairExecuteMsg::ProvideLiquidity {
+                assets,
+                slippage_tolerance,
+                receiver,
+            } => {
+                let assets: Vec<Asset> = assets
+                    .iter()
+                    .map(|asset| Asset {
+                        info: from_terraswap_info(&asset.info),
+                        amount: asset.amount,
+                    })
+                    .collect();
+                self.provide_liquidity(sender, pair, assets, funds, slippage_tolerance, receiver)
+            }
+            _ => Err(StdError::generic_err("unsupported pair message")),
+        }
+    }
+
+    /// Mints shares for the deposit the way the Terraswap pair does
+    fn provide_liquidity(
+        &mut self,
+        sender: &str,
+        pair: MockPair,
+        assets: Vec<Asset>,
+        funds: Vec<Coin>,
+        slippage_tolerance: Option<Decimal>,
+        receiver: Option<String>,
+    ) -> StdResult<Vec<Attribute>> {
+        let mut deposits = [Uint128::zero(); 2];
+        let mut pools = [Uint128::zero(); 2];
+        for (i, info) in pair.asset_infos.iter().enumerate() {
+            let deposit = assets
+                .iter()
+                .find(|asset| asset.info.equal(info))
+                .map(|asset| asset.amount)
+                .unwrap_or_default();
+            match info {
+                AssetInfo::NativeToken { denom } => {
+                    let sent = funds
+                        .iter()
+                        .find(|coin| coin.denom == *denom)
+                        .map(|coin| coin.amount)
+                        .unwrap_or_default();
+                    if sent != deposit {
+                        return Err(StdError::generic_err(
+                            "Native token balance mismatch between the argument and the transferred",
+                        ));
+                    }
+                    pools[i] = self
+                        .querier
+                        .balance(info, &pair.contract_addr)
+                        .checked_sub(deposit)?;
+                }
+                AssetInfo::Token { contract_addr } => {
+                    pools[i] = self.querier.balance(info, &pair.contract_addr);
+                    self.querier.transfer_from(
+                        contract_addr,
+                        sender,
+                        &pair.contract_addr,
+                        deposit,
+                    )?;
+                }
+            }
+            deposits[i] = deposit;
+        }
+
+        if let Some(slippage_tolerance) = slippage_tolerance {
+            let one_minus_slippage_tolerance = Decimal::one() - slippage_tolerance;
+            if Decimal::from_ratio(deposits[0] * one_minus_slippage_tolerance, deposits[1])
+                > Decimal::from_ratio(pools[0], pools[1])
+                || Decimal::from_ratio(deposits[1] * one_minus_slippage_tolerance, deposits[0])
+                    > Decimal::from_ratio(pools[1], pools[0])
+            {
+                return Err(StdError::generic_err(
+                    "Operation exceeds max splippage tolerance",
+                ));
+            }
+        }
+
+        let total_share = self.querier.total_supply(&pair.liquidity_token);
+        let share = if total_share.is_zero() {
+            initial_share(deposits[0], deposits[1])
+        } else {
+            std::cmp::min(
+                deposits[0].multiply_ratio(total_share, pools[0]),
+                deposits[1].multiply_ratio(total_share, pools[1]),
+            )
+        };
+
+        let receiver = receiver.unwrap_or_else(|| sender.to_string());
+        self.querier
+            .add(&pair.liquidity_token_info(), &receiver, share)?;
+
+        Ok(vec![
+            Attribute::new("action", "provide_liquidity"),
+            Attribute::new("share", share.to_string()),
+        ])
+    }
+
+    /// Burns the liquidity tokens sent to the pair and refunds both sides to `sender`
+    fn withdraw_liquidity(
+        &mut self,
+        sender: &str,
+        pair: MockPair,
+        share: Uint128,
+    ) -> StdResult<Vec<Attribute>> {
+        let total_share = self.querier.total_supply(&pair.liquidity_token);
+        let refunds: Vec<Asset> = pair
+            .asset_infos
+            .iter()
+            .map(|info| Asset {
+                info: info.clone(),
+                amount: self
+                    .querier
+                    .balance(info, &pair.contract_addr)
+                    .multiply_ratio(share, total_share),
+            })
+            .collect();
+
+        self.querier
+            .sub(&pair.liquidity_token_info(), &pair.contract_addr, share)?;
+        for refund in refunds.iter() {
+            self.pay_out(&pair.contract_addr, sender, refund)?;
+        }
+
+        Ok(vec![
+            Attribute::new("action", "withdraw_liquidity"),
+            Attribute::new("withdrawn_share", share.to_string()),
+        ])
+    }
+
+    /// Sends `asset` from a pair, native transfers are taxed on the amount sent
+    fn pay_out(&mut self, pair: &str, receiver: &str, asset: &Asset) -> StdResult<()> {
+        match &asset.info {
+            AssetInfo::NativeToken { denom } => {
+                let tax = if denom == "uluna" {
+                    Uint128::zero()
+                } else {
+                    let cap = self.querier.tax_caps.get(denom).copied();
+                    compute_tax(
+                        asset.amount,
+                        self.querier.tax_rate,
+                        cap.unwrap_or(Uint128::MAX),
+                    )?
+                };
+                self.querier.send_native(
+                    pair,
+                    receiver,
+                    &Coin {
+                        denom: denom.to_string(),
+                        amount: asset.amount.checked_sub(tax)?,
+                    },
+                )
+            }
+            AssetInfo::Token { .. } => {
+                self.querier
+                    .transfer(&asset.info, pair, receiver, asset.amount)
+            }
+        }
+    }
+
+    fn swap(
+        &mut self,
+        sender: &str,
+        pair: MockPair,
+        offer_asset: Asset,
+        belief_price: Option<Decimal>,
+        max_spread: Option<Decimal>,
+        to: Option<String>,
+    ) -> StdResult<Vec<Attribute>> {
+        let ask_info = pair.ask_info(&offer_asset.info)?;
+        // the offer was already transferred to the pair
+        let offer_pool = self
+            .querier
+            .balance(&offer_asset.info, &pair.contract_addr)
+            .checked_sub(offer_asset.amount)?;
+        let ask_pool = self.querier.balance(&ask_info, &pair.contract_addr);
+
+        let (return_amount, spread_amount, commission_amount) =
+            compute_swap(offer_pool, ask_pool, offer_asset.amount, pair.commission)?;
+        // like Terraswap, the spread is checked before the commission
+        assert_max_spread(
+            belief_price,
+            max_spread,
+            offer_asset.amount,
+            return_amount + commission_amount,
+            spread_amount,
+        )?;
+
+        let receiver = to.unwrap_or_else(|| sender.to_string());
+        self.pay_out(
+            &pair.contract_addr,
+            &receiver,
+            &Asset {
+                info: ask_info.clone(),
+                amount: return_amount,
+            },
+        )?;
+
+        Ok(vec![
+            Attribute::new("action", "swap"),
+            Attribute::new("offer_asset", offer_asset.info.to_string()),
+            Attribute::new("ask_asset", ask_info.to_string()),
+            Attribute::new("offer_amount", offer_asset.amount.to_string()),
+            Attribute::new("return_amount", return_amount.to_string()),
+            Attribute::new("spread_amount", spread_amount.to_string()),
+            Attribute::new("commission_amount", commission_amount.to_string()),
+        ])
+    }
+}
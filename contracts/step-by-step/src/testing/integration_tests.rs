@@ -0,0 +1,1948 @@
+use cosmwasm_std::{from_slice, to_binary, Attribute, Coin, Decimal, Uint128};
+use cw20::{Cw20ExecuteMsg, Expiration};
+
+use crate::asset::{Asset, AssetInfo};
+use crate::msg::{
+    AllowlistKind, ArbitragesResponse, ConfigResponse, Cw20HookMsg, DcaResponse, DcaRunsResponse,
+    ExecuteMsg, ExecutorAccess, InstantiateMsg, OrderTrigger, OrdersResponse, PairEntry,
+    PairsResponse, QueryMsg, ReferencePrice, RoutesResponse, SimulateStrategyResponse,
+    StrategyStep, TriggerCondition,
+};
+use crate::msgs::best_venue::{BestVenueSwapMsg, SwapVenue};
+use crate::msgs::liquidity::{ProvideLiquidityMsg, WithdrawLiquidityMsg};
+use crate::msgs::liquidity_pool::LiquidityPoolSwapMsg;
+use crate::msgs::market::MarketSwapMsg;
+use crate::operations::StrategyStepOperation;
+use crate::testing::{MockBorrowerMsg, MockChain};
+
+const OWNER: &str = "owner";
+const USER: &str = "user";
+const FACTORY: &str = "factory";
+const MIRROR: &str = "mirror";
+const LUNA_UST_LP: &str = "luna-ust-lp";
+const LENDER: &str = "lender";
+const BORROWER: &str = "borrower";
+
+fn native(denom: &str) -> AssetInfo {
+    AssetInfo::NativeToken {
+        denom: denom.to_string(),
+    }
+}
+
+fn mirror() -> AssetInfo {
+    AssetInfo::Token {
+        contract_addr: MIRROR.to_string(),
+    }
+}
+
+fn luna_ust_lp() -> AssetInfo {
+    AssetInfo::Token {
+        contract_addr: LUNA_UST_LP.to_string(),
+    }
+}
+
+fn asset(info: AssetInfo, amount: u128) -> Asset {
+    Asset {
+        info,
+        amount: Uint128::new(amount),
+    }
+}
+
+fn market_step(from: AssetInfo, to: AssetInfo) -> StrategyStep {
+    StrategyStep {
+        from_asset: from,
+        to_asset: to,
+        operation: StrategyStepOperation::MarketSwapOperation {
+            msg: MarketSwapMsg {},
+        },
+        max_oracle_deviation: None,
+    }
+}
+
+fn pool_step(from: AssetInfo, to: AssetInfo, msg: &str) -> StrategyStep {
+    StrategyStep {
+        from_asset: from,
+        to_asset: to,
+        operation: StrategyStepOperation::LiquidityPoolSwapOperation {
+            msg: from_slice(msg.as_bytes()).unwrap(),
+        },
+        max_oracle_deviation: None,
+    }
+}
+
+fn attribute<'a>(attributes: &'a [Attribute], key: &str) -> Vec<&'a str> {
+    attributes
+        .iter()
+        .filter(|attr| attr.key == key)
+        .map(|attr| attr.value.as_str())
+        .collect()
+}
+
+/// 1 luna = 100 ust on the market, 10 mir or 90 ust on Terraswap
+fn setup() -> MockChain {
+    let mut chain = MockChain::new();
+    chain
+        .instantiate(OWNER, InstantiateMsg { comission: 6 })
+        .unwrap();
+    chain
+        .querier()
+        .with_tax(Decimal::permille(1), &[Coin::new(1_000_000, "uusd")]);
+    chain
+        .querier()
+        .with_exchange_rates(&[("uusd", Decimal::from_ratio(100u128, 1u128))]);
+
+    chain.querier().with_pair(
+        FACTORY,
+        "luna-mirror",
+        [
+            asset(native("uluna"), 1_000_000_000),
+            asset(mirror(), 10_000_000_000),
+        ],
+        Decimal::permille(3),
+    );
+    chain.querier().with_pair(
+        FACTORY,
+        "mirror-ust",
+        [
+            asset(mirror(), 10_000_000_000),
+            asset(native("uusd"), 100_000_000_000),
+        ],
+        Decimal::permille(3),
+    );
+    chain.querier().with_pair(
+        FACTORY,
+        "luna-ust",
+        [
+            asset(native("uluna"), 1_000_000_000),
+            asset(native("uusd"), 90_000_000_000),
+        ],
+        Decimal::permille(3),
+    );
+
+    let router = chain.router();
+    for (kind, address) in [
+        (AllowlistKind::Factory, FACTORY),
+        (AllowlistKind::Token, MIRROR),
+        (AllowlistKind::Token, LUNA_UST_LP),
+    ]
+    .iter()
+    {
+        chain
+            .execute(
+                OWNER,
+                router.as_str(),
+                &ExecuteMsg::UpdateAllowlist {
+                    kind: kind.clone(),
+                    add: vec![address.to_string()],
+                    remove: vec![],
+                },
+                &[],
+            )
+            .unwrap();
+    }
+
+    chain.set_balance(USER, &native("uusd"), Uint128::new(10_000_000_000));
+    chain.set_balance(USER, &mirror(), Uint128::new(10_000_000_000));
+    chain
+}
+
+fn ust_mirror_ust_route() -> Vec<StrategyStep> {
+    let factory = r#"{"factory_addr":"factory"}"#;
+    vec![
+        market_step(native("uusd"), native("uluna")),
+        pool_step(native("uluna"), mirror(), factory),
+        pool_step(mirror(), native("uusd"), factory),
+    ]
+}
+
+#[test]
+fn native_multi_hop_strategy() {
+    let mut chain = setup();
+    let router = chain.router();
+    let steps = ust_mirror_ust_route();
+
+    let simulation: SimulateStrategyResponse = chain
+        .query(QueryMsg::SimulateStrategy {
+            steps: steps.clone(),
+            amount: Uint128::new(1_000_000),
+        })
+        .unwrap();
+    assert_eq!(3, simulation.amounts.len());
+    assert_eq!(Uint128::new(10_000), simulation.amounts[0]);
+
+    let attributes = chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategy {
+                steps,
+                minimum_receive: Uint128::new(900_000),
+            },
+            &[Coin::new(1_000_000, "uusd")],
+        )
+        .unwrap();
+    assert_eq!(vec!["1", "2", "3"], attribute(&attributes, "step_index"));
+    assert_eq!(
+        vec!["market", "luna-mirror", "mirror-ust"],
+        attribute(&attributes, "pair_address")
+    );
+
+    // the caller paid the offer and its tax, and received the simulated amount
+    let received = Uint128::new(10_000_000_000 - 1_000_000 - 1_000);
+    assert_eq!(
+        received + simulation.return_amount,
+        chain.balance(USER, &native("uusd"))
+    );
+    assert_eq!(
+        vec![chain.balance(USER, &native("uusd")).to_string()],
+        attribute(&attributes, "final_balance")
+    );
+
+    // nothing is left behind in the router
+    for info in [native("uusd"), native("uluna"), mirror()].iter() {
+        assert_eq!(Uint128::zero(), chain.balance(router.as_str(), info));
+    }
+}
+
+#[test]
+fn cw20_offer_strategy() {
+    let mut chain = setup();
+    let router = chain.router();
+    let factory = r#"{"factory_addr":"factory"}"#;
+    let steps = vec![
+        pool_step(mirror(), native("uusd"), factory),
+        market_step(native("uusd"), native("uluna")),
+    ];
+
+    let attributes = chain
+        .execute(
+            USER,
+            MIRROR,
+            &Cw20ExecuteMsg::Send {
+                contract: router.to_string(),
+                amount: Uint128::new(1_000_000),
+                msg: to_binary(&Cw20HookMsg::ExecuteStrategy {
+                    steps,
+                    minimum_receive: Uint128::new(90_000),
+                })
+                .unwrap(),
+            },
+            &[],
+        )
+        .unwrap();
+    assert!(attribute(&attributes, "action").contains(&"finalize_strategy"));
+
+    assert_eq!(
+        Uint128::new(10_000_000_000 - 1_000_000),
+        chain.balance(USER, &mirror())
+    );
+    let received = chain.balance(USER, &native("uluna"));
+    assert!(received >= Uint128::new(90_000) && received < Uint128::new(100_000));
+    assert_eq!(Uint128::zero(), chain.balance(router.as_str(), &mirror()));
+}
+
+#[test]
+fn minimum_receive_reverts_everything() {
+    let mut chain = setup();
+    let router = chain.router();
+
+    let err = chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategy {
+                steps: ust_mirror_ust_route(),
+                minimum_receive: Uint128::new(999_000),
+            },
+            &[Coin::new(1_000_000, "uusd")],
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("minimum receive amount"));
+
+    assert_eq!(
+        Uint128::new(10_000_000_000),
+        chain.balance(USER, &native("uusd"))
+    );
+    assert_eq!(
+        Uint128::new(1_000_000_000),
+        chain.balance("luna-mirror", &native("uluna"))
+    );
+    assert_eq!(
+        Uint128::zero(),
+        chain.balance(router.as_str(), &native("uluna"))
+    );
+
+    // the execution counter was rolled back too
+    let attributes = chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategy {
+                steps: ust_mirror_ust_route(),
+                minimum_receive: Uint128::new(1),
+            },
+            &[Coin::new(1_000_000, "uusd")],
+        )
+        .unwrap();
+    assert_eq!(vec!["1"], {
+        let mut ids = attribute(&attributes, "execution_id");
+        ids.dedup();
+        ids
+    });
+}
+
+#[test]
+fn max_spread_reverts() {
+    let mut chain = setup();
+    let router = chain.router();
+    chain.set_balance(USER, &native("uluna"), Uint128::new(1_000_000));
+
+    // expects 100 mir per luna while the pool gives 10
+    let steps = vec![pool_step(
+        native("uluna"),
+        mirror(),
+        r#"{"factory_addr":"factory","belief_price":"0.01","max_spread":"0.01"}"#,
+    )];
+    let err = chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategy {
+                steps,
+                minimum_receive: Uint128::new(1),
+            },
+            &[Coin::new(1_000_000, "uluna")],
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("max spread"));
+    assert_eq!(
+        Uint128::new(1_000_000),
+        chain.balance(USER, &native("uluna"))
+    );
+}
+
+#[test]
+fn missing_pair_fails() {
+    let mut chain = setup();
+    let router = chain.router();
+
+    let steps = vec![pool_step(
+        native("uusd"),
+        native("ukrw"),
+        r#"{"factory_addr":"factory"}"#,
+    )];
+    let res = chain.execute(
+        USER,
+        router.as_str(),
+        &ExecuteMsg::ExecuteStrategy {
+            steps,
+            minimum_receive: Uint128::new(1),
+        },
+        &[Coin::new(1_000_000, "uusd")],
+    );
+    assert!(res.is_err());
+    assert_eq!(
+        Uint128::new(10_000_000_000),
+        chain.balance(USER, &native("uusd"))
+    );
+}
+
+#[test]
+fn inventory_arbitrage() {
+    let mut chain = setup();
+    let router = chain.router();
+    chain.set_balance(OWNER, &native("uusd"), Uint128::new(2_000_000_000));
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::Deposit {},
+            &[Coin::new(1_000_000_000, "uusd")],
+        )
+        .unwrap();
+
+    // luna is cheaper on Terraswap than on the market
+    let steps = vec![
+        pool_step(
+            native("uusd"),
+            native("uluna"),
+            r#"{"factory_addr":"factory"}"#,
+        ),
+        market_step(native("uluna"), native("uusd")),
+    ];
+    let msg = ExecuteMsg::ExecuteInventoryStrategy {
+        steps,
+        amount: Uint128::new(100_000_000),
+        minimum_profit: Uint128::new(1_000_000),
+    };
+    assert!(chain.execute(USER, router.as_str(), &msg, &[]).is_err());
+    chain.execute(OWNER, router.as_str(), &msg, &[]).unwrap();
+
+    let inventory = chain.balance(router.as_str(), &native("uusd"));
+    assert!(inventory >= Uint128::new(1_001_000_000));
+    assert_eq!(
+        Uint128::zero(),
+        chain.balance(router.as_str(), &native("uluna"))
+    );
+
+    // a second run on the same pool is no longer profitable enough
+    let msg = ExecuteMsg::ExecuteInventoryStrategy {
+        steps: vec![
+            pool_step(
+                native("uusd"),
+                native("uluna"),
+                r#"{"factory_addr":"factory"}"#,
+            ),
+            market_step(native("uluna"), native("uusd")),
+        ],
+        amount: Uint128::new(900_000_000),
+        minimum_profit: Uint128::new(100_000_000),
+    };
+    assert!(chain.execute(OWNER, router.as_str(), &msg, &[]).is_err());
+    assert_eq!(inventory, chain.balance(router.as_str(), &native("uusd")));
+}
+
+#[test]
+fn market_hop_sent_out_simulates_net_of_tax() {
+    let mut chain = setup();
+    let router = chain.router();
+    let steps = vec![market_step(native("uusd"), native("uluna"))];
+
+    let simulation: SimulateStrategyResponse = chain
+        .query(QueryMsg::SimulateStrategy {
+            steps: steps.clone(),
+            amount: Uint128::new(1_000_000),
+        })
+        .unwrap();
+    assert!(simulation.return_amount < Uint128::new(10_000));
+
+    chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategy {
+                steps,
+                minimum_receive: simulation.return_amount,
+            },
+            &[Coin::new(1_000_000, "uusd")],
+        )
+        .unwrap();
+    assert_eq!(
+        simulation.return_amount,
+        chain.balance(USER, &native("uluna"))
+    );
+}
+
+#[test]
+fn oracle_deviation_guards_native_hop() {
+    let mut chain = setup();
+    let router = chain.router();
+    chain.set_balance(USER, &native("uluna"), Uint128::new(2_000_000));
+
+    // Terraswap gives 90 ust per luna while the oracle says 100
+    let step = |max_deviation: u64| StrategyStep {
+        max_oracle_deviation: Some(Decimal::percent(max_deviation)),
+        ..pool_step(
+            native("uluna"),
+            native("uusd"),
+            r#"{"factory_addr":"factory"}"#,
+        )
+    };
+    let execute = |chain: &mut MockChain, max_deviation: u64| {
+        chain.execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategy {
+                steps: vec![step(max_deviation)],
+                minimum_receive: Uint128::new(1),
+            },
+            &[Coin::new(1_000_000, "uluna")],
+        )
+    };
+
+    let err = execute(&mut chain, 5).unwrap_err();
+    assert!(err.to_string().contains("oracle rate: 100"));
+    assert_eq!(
+        Uint128::new(2_000_000),
+        chain.balance(USER, &native("uluna"))
+    );
+
+    let attributes = execute(&mut chain, 15).unwrap();
+    assert_eq!(vec!["100"], attribute(&attributes, "oracle_rate"));
+    let rate: Decimal = attribute(&attributes, "rate")[0].parse().unwrap();
+    assert!(rate > Decimal::from_ratio(85u128, 1u128) && rate < Decimal::from_ratio(90u128, 1u128));
+}
+
+#[test]
+fn oracle_deviation_uses_reference_price_for_cw20() {
+    let mut chain = setup();
+    let router = chain.router();
+    let mut step = pool_step(mirror(), native("uusd"), r#"{"factory_addr":"factory"}"#);
+    step.max_oracle_deviation = Some(Decimal::percent(5));
+    let send = |chain: &mut MockChain| {
+        chain.execute(
+            USER,
+            MIRROR,
+            &Cw20ExecuteMsg::Send {
+                contract: router.to_string(),
+                amount: Uint128::new(1_000_000),
+                msg: to_binary(&Cw20HookMsg::ExecuteStrategy {
+                    steps: vec![step.clone()],
+                    minimum_receive: Uint128::new(1),
+                })
+                .unwrap(),
+            },
+            &[],
+        )
+    };
+    let set_reference = |chain: &mut MockChain, price: u128| {
+        chain
+            .execute(
+                OWNER,
+                router.as_str(),
+                &ExecuteMsg::UpdateReferencePrice {
+                    token: MIRROR.to_string(),
+                    price: Some(ReferencePrice {
+                        denom: "uluna".to_string(),
+                        price: Decimal::from_ratio(price, 100u128),
+                    }),
+                },
+                &[],
+            )
+            .unwrap();
+    };
+
+    let err = send(&mut chain).unwrap_err();
+    assert!(err.to_string().contains("no reference price for mirror"));
+
+    // 0.2 luna per mir is worth 20 ust, the pool only gives 10
+    set_reference(&mut chain, 20);
+    let err = send(&mut chain).unwrap_err();
+    assert!(err.to_string().contains("oracle rate: 20"));
+    assert_eq!(Uint128::new(10_000_000_000), chain.balance(USER, &mirror()));
+
+    set_reference(&mut chain, 10);
+    let attributes = send(&mut chain).unwrap();
+    assert_eq!(vec!["10"], attribute(&attributes, "oracle_rate"));
+}
+
+#[test]
+fn max_spread_defaults_and_auto_belief_price() {
+    let mut chain = setup();
+    let router = chain.router();
+    chain.set_balance(USER, &native("uluna"), Uint128::new(400_000_000));
+    let update_default_max_spread = |chain: &mut MockChain, max_spread: Option<Decimal>| {
+        chain.execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::UpdateDefaultMaxSpread { max_spread },
+            &[],
+        )
+    };
+    update_default_max_spread(&mut chain, Some(Decimal::percent(1))).unwrap();
+
+    let luna_mirror = |msg: LiquidityPoolSwapMsg| StrategyStep {
+        from_asset: native("uluna"),
+        to_asset: mirror(),
+        operation: StrategyStepOperation::LiquidityPoolSwapOperation { msg },
+        max_oracle_deviation: None,
+    };
+    let execute = |chain: &mut MockChain, msg: LiquidityPoolSwapMsg| {
+        chain.execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategy {
+                steps: vec![luna_mirror(msg)],
+                minimum_receive: Uint128::new(1),
+            },
+            &[Coin::new(100_000_000, "uluna")],
+        )
+    };
+
+    // a tenth of the pool moves the price well beyond the router default
+    let err = execute(&mut chain, LiquidityPoolSwapMsg::new(FACTORY)).unwrap_err();
+    assert!(err.to_string().contains("max spread"));
+
+    // the strategy max spread takes precedence over the default
+    chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::RegisterStrategy {
+                name: "luna-mirror".to_string(),
+                steps: vec![luna_mirror(LiquidityPoolSwapMsg::new(FACTORY))],
+                global: None,
+                max_spread: Some(Decimal::percent(15)),
+            },
+            &[],
+        )
+        .unwrap();
+    chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategyById {
+                id: 1,
+                amount: Uint128::new(100_000_000),
+                minimum_receive: Uint128::new(1),
+            },
+            &[Coin::new(100_000_000, "uluna")],
+        )
+        .unwrap();
+
+    // a belief price quoted before the previous swaps fails, the simulated one does not
+    let strict = LiquidityPoolSwapMsg::new(FACTORY).with_max_spread(Decimal::permille(1));
+    let err = execute(
+        &mut chain,
+        strict.clone().with_belief_price(Decimal::percent(11)),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("max spread"));
+    execute(&mut chain, strict.with_auto_belief_price()).unwrap();
+    assert_eq!(
+        Uint128::new(200_000_000),
+        chain.balance(USER, &native("uluna"))
+    );
+
+    let invalid = LiquidityPoolSwapMsg {
+        belief_price: Some(Decimal::percent(11)),
+        ..LiquidityPoolSwapMsg::new(FACTORY).with_auto_belief_price()
+    };
+    assert!(execute(&mut chain, invalid).is_err());
+
+    // without a default the swap only follows the pair max spread
+    update_default_max_spread(&mut chain, None).unwrap();
+    let config: ConfigResponse = chain.query(QueryMsg::Config {}).unwrap();
+    assert_eq!(None, config.default_max_spread);
+    execute(&mut chain, LiquidityPoolSwapMsg::new(FACTORY)).unwrap();
+}
+
+fn provide_step(from: AssetInfo, pair_asset: AssetInfo, zap: bool) -> StrategyStep {
+    StrategyStep {
+        from_asset: from,
+        to_asset: luna_ust_lp(),
+        operation: StrategyStepOperation::ProvideLiquidityOperation {
+            msg: ProvideLiquidityMsg {
+                factory_addr: FACTORY.to_string(),
+                pair_asset,
+                slippage_tolerance: None,
+                zap: Some(zap),
+                max_spread: None,
+            },
+        },
+        max_oracle_deviation: None,
+    }
+}
+
+fn withdraw_step(to: AssetInfo, pair_asset: AssetInfo, zap: bool) -> StrategyStep {
+    StrategyStep {
+        from_asset: luna_ust_lp(),
+        to_asset: to,
+        operation: StrategyStepOperation::WithdrawLiquidityOperation {
+            msg: WithdrawLiquidityMsg {
+                factory_addr: FACTORY.to_string(),
+                pair_asset,
+                zap: Some(zap),
+                max_spread: None,
+            },
+        },
+        max_oracle_deviation: None,
+    }
+}
+
+#[test]
+fn zap_in_and_out_of_liquidity() {
+    let mut chain = setup();
+    let router = chain.router();
+    let steps = vec![provide_step(native("uusd"), native("uluna"), true)];
+
+    let simulation: SimulateStrategyResponse = chain
+        .query(QueryMsg::SimulateStrategy {
+            steps: steps.clone(),
+            amount: Uint128::new(100_000_000),
+        })
+        .unwrap();
+    let attributes = chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategy {
+                steps,
+                minimum_receive: Uint128::new(1),
+            },
+            &[Coin::new(100_000_000, "uusd")],
+        )
+        .unwrap();
+    assert!(attribute(&attributes, "action").contains(&"swap"));
+    assert!(attribute(&attributes, "action").contains(&"provide_liquidity"));
+
+    // the share is measured from the liquidity token balance
+    let share = chain.balance(USER, &luna_ust_lp());
+    assert!(!share.is_zero());
+    assert_eq!(
+        vec![share.to_string()],
+        attribute(&attributes, "final_balance")
+    );
+    let difference = std::cmp::max(share, simulation.return_amount)
+        - std::cmp::min(share, simulation.return_amount);
+    assert!(difference <= Uint128::new(share.u128() / 1_000));
+    // rounding the tax of each transfer leaves a unit behind at most
+    assert!(chain.balance(router.as_str(), &native("uusd")) <= Uint128::new(2));
+    for info in [native("uluna"), luna_ust_lp()].iter() {
+        assert_eq!(Uint128::zero(), chain.balance(router.as_str(), info));
+    }
+
+    // the pair asset side is swapped back when withdrawing, with half the pool withdrawn
+    // the swap has to be simulated on the reserves left after the withdrawal
+    let half = chain
+        .balance(FACTORY, &luna_ust_lp())
+        .multiply_ratio(1u128, 2u128);
+    let factory_share = chain.balance(FACTORY, &luna_ust_lp());
+    chain.set_balance(FACTORY, &luna_ust_lp(), factory_share - half);
+    chain.set_balance(USER, &luna_ust_lp(), share + half);
+    let share = chain.balance(USER, &luna_ust_lp());
+    let withdraw_steps = vec![withdraw_step(native("uusd"), native("uluna"), true)];
+    let simulation: SimulateStrategyResponse = chain
+        .query(QueryMsg::SimulateStrategy {
+            steps: withdraw_steps.clone(),
+            amount: share,
+        })
+        .unwrap();
+    let ust = chain.balance(USER, &native("uusd"));
+    chain
+        .execute(
+            USER,
+            LUNA_UST_LP,
+            &Cw20ExecuteMsg::Send {
+                contract: router.to_string(),
+                amount: share,
+                msg: to_binary(&Cw20HookMsg::ExecuteStrategy {
+                    steps: withdraw_steps,
+                    minimum_receive: Uint128::new(95_000_000),
+                })
+                .unwrap(),
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(Uint128::zero(), chain.balance(USER, &luna_ust_lp()));
+    let received = chain.balance(USER, &native("uusd")) - ust;
+    assert!(received > Uint128::new(95_000_000));
+    // forwarding the withdrawn side to the user is taxed once more, up to the cap
+    assert!(received <= simulation.return_amount);
+    assert!(simulation.return_amount - received <= Uint128::new(1_000_010));
+    for info in [native("uluna"), luna_ust_lp()].iter() {
+        assert_eq!(Uint128::zero(), chain.balance(router.as_str(), info));
+    }
+}
+
+#[test]
+fn liquidity_without_zap_uses_inventory() {
+    let mut chain = setup();
+    let router = chain.router();
+    chain.set_balance(OWNER, &native("uusd"), Uint128::new(2_000_000_000));
+    chain.set_balance(OWNER, &native("uluna"), Uint128::new(1_000_000_000));
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::Deposit {},
+            &[
+                Coin::new(1_000_000, "uluna"),
+                Coin::new(1_000_000_000, "uusd"),
+            ],
+        )
+        .unwrap();
+
+    let steps = vec![
+        provide_step(native("uusd"), native("uluna"), false),
+        withdraw_step(native("uusd"), native("uluna"), true),
+    ];
+    let err = chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategy {
+                steps: steps.clone(),
+                minimum_receive: Uint128::new(1),
+            },
+            &[Coin::new(90_000_000, "uusd")],
+        )
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("provide_liquidity uses the router inventory"));
+
+    // the luna side comes from the inventory and is swapped back into ust on withdrawal
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteInventoryStrategy {
+                steps,
+                amount: Uint128::new(90_000_000),
+                minimum_profit: Uint128::new(1),
+            },
+            &[],
+        )
+        .unwrap();
+    let luna = chain.balance(router.as_str(), &native("uluna"));
+    assert!(luna < Uint128::new(1_000_000) && luna > Uint128::zero());
+    assert_eq!(
+        Uint128::zero(),
+        chain.balance(router.as_str(), &luna_ust_lp())
+    );
+    assert!(chain.balance(router.as_str(), &native("uusd")) > Uint128::new(1_000_000_000));
+}
+
+#[test]
+fn finds_routes_through_registered_pairs() {
+    let mut chain = setup();
+    let router = chain.router();
+    let update_pairs = ExecuteMsg::UpdatePairs {
+        add: vec![
+            PairEntry {
+                factory_addr: FACTORY.to_string(),
+                asset_infos: [native("uluna"), mirror()],
+            },
+            PairEntry {
+                factory_addr: FACTORY.to_string(),
+                asset_infos: [mirror(), native("uusd")],
+            },
+            PairEntry {
+                factory_addr: FACTORY.to_string(),
+                asset_infos: [native("uusd"), native("uluna")],
+            },
+        ],
+        remove: vec![],
+    };
+    assert!(chain
+        .execute(USER, router.as_str(), &update_pairs, &[])
+        .is_err());
+    chain
+        .execute(OWNER, router.as_str(), &update_pairs, &[])
+        .unwrap();
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::UpdateConfig {
+                owner: None,
+                permissionless: None,
+                executor_access: None,
+                market_denoms: Some(vec!["uluna".to_string(), "uusd".to_string()]),
+                order_bounty: None,
+                dca_bounty: None,
+                arbitrage_bounty: None,
+                flash_loan_fee: None,
+            },
+            &[],
+        )
+        .unwrap();
+
+    let pairs: PairsResponse = chain
+        .query(QueryMsg::Pairs {
+            start_after: None,
+            limit: None,
+        })
+        .unwrap();
+    assert_eq!(
+        vec!["luna-mirror", "luna-ust", "mirror-ust"],
+        pairs
+            .pairs
+            .iter()
+            .map(|pair| pair.contract_addr.as_str())
+            .collect::<Vec<&str>>()
+    );
+
+    let find_routes = |chain: &MockChain, max_hops: u32| -> RoutesResponse {
+        chain
+            .query(QueryMsg::FindRoutes {
+                offer_asset: native("uusd"),
+                ask_asset: mirror(),
+                max_hops,
+                amount: Uint128::new(1_000_000),
+                limit: Some(10),
+            })
+            .unwrap()
+    };
+    assert_eq!(1, find_routes(&chain, 1).routes.len());
+    assert!(chain
+        .query::<RoutesResponse>(QueryMsg::FindRoutes {
+            offer_asset: native("uusd"),
+            ask_asset: mirror(),
+            max_hops: 5,
+            amount: Uint128::new(1_000_000),
+            limit: None,
+        })
+        .is_err());
+
+    // luna is cheaper on the luna-ust pair than on the market
+    let routes = find_routes(&chain, 2).routes;
+    assert_eq!(3, routes.len());
+    assert!(routes
+        .windows(2)
+        .all(|pair| pair[0].return_amount >= pair[1].return_amount));
+    let best = routes[0].clone();
+    assert_eq!(
+        vec![native("uusd"), native("uluna")],
+        vec![
+            best.steps[0].from_asset.clone(),
+            best.steps[0].to_asset.clone()
+        ]
+    );
+    assert!(matches!(
+        best.steps[0].operation,
+        StrategyStepOperation::LiquidityPoolSwapOperation { .. }
+    ));
+
+    // the best route is ready to execute and returns what was simulated
+    chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategy {
+                steps: best.steps,
+                minimum_receive: best.return_amount,
+            },
+            &[Coin::new(1_000_000, "uusd")],
+        )
+        .unwrap();
+    assert_eq!(
+        Uint128::new(10_000_000_000) + best.return_amount,
+        chain.balance(USER, &mirror())
+    );
+
+    // removed pairs are no longer searched
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::UpdatePairs {
+                add: vec![],
+                remove: vec!["luna-ust".to_string()],
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(2, find_routes(&chain, 2).routes.len());
+}
+
+#[test]
+fn executes_best_candidate() {
+    let mut chain = setup();
+    let router = chain.router();
+    let factory = r#"{"factory_addr":"factory"}"#;
+    let candidates = vec![
+        vec![pool_step(native("uusd"), mirror(), factory)],
+        vec![
+            market_step(native("uusd"), native("uluna")),
+            pool_step(native("uluna"), mirror(), factory),
+        ],
+        vec![
+            pool_step(native("uusd"), native("uluna"), factory),
+            pool_step(native("uluna"), mirror(), factory),
+        ],
+    ];
+
+    let mismatched = ExecuteMsg::ExecuteBestStrategy {
+        candidates: vec![
+            candidates[0].clone(),
+            vec![market_step(native("uusd"), native("uluna"))],
+        ],
+        minimum_receive: Uint128::zero(),
+    };
+    assert!(chain
+        .execute(
+            USER,
+            router.as_str(),
+            &mismatched,
+            &[Coin::new(1_000_000, "uusd")]
+        )
+        .is_err());
+
+    let simulations: Vec<Uint128> = candidates
+        .iter()
+        .map(|steps| {
+            chain
+                .query::<SimulateStrategyResponse>(QueryMsg::SimulateStrategy {
+                    steps: steps.clone(),
+                    amount: Uint128::new(1_000_000),
+                })
+                .unwrap()
+                .return_amount
+        })
+        .collect();
+    // luna is cheaper on the luna-ust pair than on the market
+    assert!(simulations[2] > simulations[0] && simulations[2] > simulations[1]);
+
+    // candidates are only simulated on the sent offer, never on the router inventory
+    chain.set_balance(router.as_str(), &native("uusd"), Uint128::new(50_000_000));
+    assert!(chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteBestStrategy {
+                candidates: candidates.clone(),
+                minimum_receive: Uint128::new(1),
+            },
+            &[]
+        )
+        .is_err());
+    assert_eq!(
+        Uint128::new(50_000_000),
+        chain.balance(router.as_str(), &native("uusd"))
+    );
+
+    let attributes = chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteBestStrategy {
+                candidates,
+                minimum_receive: Uint128::new(1),
+            },
+            &[Coin::new(1_000_000, "uusd")],
+        )
+        .unwrap();
+    assert_eq!(vec!["2"], attribute(&attributes, "candidate"));
+    assert_eq!(
+        vec![simulations[2].to_string()],
+        attribute(&attributes, "simulated_amount")
+    );
+    assert_eq!(
+        vec!["luna-ust", "luna-mirror"],
+        attribute(&attributes, "pair_address")
+    );
+    assert_eq!(
+        Uint128::new(10_000_000_000) + simulations[2],
+        chain.balance(USER, &mirror())
+    );
+}
+
+#[test]
+fn best_venue_swap_picks_highest_return() {
+    let mut chain = setup();
+    let router = chain.router();
+    // mirror is cheaper on both other venues, cheapest through the second factory
+    chain.querier().with_pair(
+        "factory2",
+        "luna-mirror-2",
+        [
+            asset(native("uluna"), 1_000_000_000),
+            asset(mirror(), 12_000_000_000),
+        ],
+        Decimal::permille(3),
+    );
+    chain.querier().with_pair(
+        "factory3",
+        "luna-mirror-3",
+        [
+            asset(native("uluna"), 1_000_000_000),
+            asset(mirror(), 11_000_000_000),
+        ],
+        Decimal::permille(3),
+    );
+    let step = |venues: Vec<SwapVenue>| StrategyStep {
+        from_asset: native("uluna"),
+        to_asset: mirror(),
+        operation: StrategyStepOperation::BestVenueSwapOperation {
+            msg: BestVenueSwapMsg {
+                venues,
+                max_spread: None,
+                auto_belief_price: Some(true),
+            },
+        },
+        max_oracle_deviation: None,
+    };
+    let venues = vec![
+        SwapVenue::Factory {
+            factory_addr: FACTORY.to_string(),
+        },
+        SwapVenue::Pair {
+            pair_addr: "luna-mirror-3".to_string(),
+        },
+        SwapVenue::Factory {
+            factory_addr: "factory2".to_string(),
+        },
+        // no luna-mirror pair
+        SwapVenue::Factory {
+            factory_addr: "factory4".to_string(),
+        },
+    ];
+    let execute = |chain: &mut MockChain, venues: Vec<SwapVenue>| {
+        chain.execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteStrategy {
+                steps: vec![step(venues)],
+                minimum_receive: Uint128::new(1),
+            },
+            &[Coin::new(1_000_000, "uluna")],
+        )
+    };
+    chain.set_balance(USER, &native("uluna"), Uint128::new(10_000_000));
+
+    // venues that are not allowlisted fail the step instead of being skipped
+    let attributes = execute(&mut chain, venues[..1].to_vec()).unwrap();
+    assert_eq!(vec!["0"], attribute(&attributes, "venue"));
+    let err = execute(&mut chain, venues.clone()).unwrap_err();
+    assert!(err.to_string().contains("pair not allowed: luna-mirror-3"));
+
+    for (kind, address) in [
+        (AllowlistKind::Factory, "factory2"),
+        (AllowlistKind::Factory, "factory4"),
+        (AllowlistKind::Pair, "luna-mirror-3"),
+    ]
+    .iter()
+    {
+        chain
+            .execute(
+                OWNER,
+                router.as_str(),
+                &ExecuteMsg::UpdateAllowlist {
+                    kind: kind.clone(),
+                    add: vec![address.to_string()],
+                    remove: vec![],
+                },
+                &[],
+            )
+            .unwrap();
+    }
+
+    let simulation: SimulateStrategyResponse = chain
+        .query(QueryMsg::SimulateStrategy {
+            steps: vec![step(venues.clone())],
+            amount: Uint128::new(1_000_000),
+        })
+        .unwrap();
+    let before = chain.balance(USER, &mirror());
+    let attributes = execute(&mut chain, venues).unwrap();
+    assert_eq!(vec!["2"], attribute(&attributes, "venue"));
+    assert_eq!(
+        vec!["luna-mirror-2"],
+        attribute(&attributes, "pair_address")
+    );
+    assert_eq!(
+        before + simulation.return_amount,
+        chain.balance(USER, &mirror())
+    );
+}
+
+#[test]
+fn limit_orders_are_escrowed_and_filled_by_keepers() {
+    let mut chain = setup();
+    let router = chain.router();
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::UpdateConfig {
+                owner: None,
+                permissionless: None,
+                executor_access: None,
+                market_denoms: None,
+                order_bounty: Some(Decimal::percent(1)),
+                dca_bounty: None,
+                arbitrage_bounty: None,
+                flash_loan_fee: None,
+            },
+            &[],
+        )
+        .unwrap();
+
+    let place_order = |chain: &mut MockChain, min_output: u128, expiry: Expiration| {
+        chain.execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::PlaceOrder {
+                steps: vec![pool_step(
+                    native("uusd"),
+                    mirror(),
+                    r#"{"factory_addr":"factory"}"#,
+                )],
+                offer: asset(native("uusd"), 1_000_000),
+                min_output: Uint128::new(min_output),
+                expiry,
+            },
+            &[Coin::new(1_000_000, "uusd")],
+        )
+    };
+    let orders = |chain: &MockChain| -> Vec<u64> {
+        chain
+            .query::<OrdersResponse>(QueryMsg::Orders {
+                owner: Some(USER.to_string()),
+                start_after: None,
+                limit: None,
+            })
+            .unwrap()
+            .orders
+            .iter()
+            .map(|order| order.id)
+            .collect()
+    };
+
+    // about 99_600 mirror at the current price
+    let attributes = place_order(&mut chain, 105_000, Expiration::Never {}).unwrap();
+    assert_eq!(vec!["1"], attribute(&attributes, "order_id"));
+    assert_eq!(vec![1], orders(&chain));
+    assert!(chain
+        .execute(
+            "keeper",
+            router.as_str(),
+            &ExecuteMsg::ExecuteOrder { id: 1 },
+            &[]
+        )
+        .is_err());
+
+    // the escrow is not part of the inventory
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::Sweep {
+                assets: vec![native("uusd")],
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert!(chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::Withdraw {
+                asset: asset(native("uusd"), 1),
+                recipient: None,
+            },
+            &[],
+        )
+        .is_err());
+    assert_eq!(
+        Uint128::new(1_000_000),
+        chain.balance(router.as_str(), &native("uusd"))
+    );
+
+    // mirror gets cheaper
+    chain.set_balance("mirror-ust", &mirror(), Uint128::new(11_000_000_000));
+    let user_mirror = chain.balance(USER, &mirror());
+    let attributes = chain
+        .execute(
+            "keeper",
+            router.as_str(),
+            &ExecuteMsg::ExecuteOrder { id: 1 },
+            &[],
+        )
+        .unwrap();
+    let output: Uint128 = attribute(&attributes, "output")[0].parse().unwrap();
+    let bounty: Uint128 = attribute(&attributes, "bounty")[0].parse().unwrap();
+    assert!(output >= Uint128::new(105_000));
+    assert_eq!((output + bounty) * Decimal::percent(1), bounty);
+    assert_eq!(bounty, chain.balance("keeper", &mirror()));
+    assert_eq!(user_mirror + output, chain.balance(USER, &mirror()));
+    assert!(orders(&chain).is_empty());
+    assert_eq!(Uint128::zero(), chain.balance(router.as_str(), &mirror()));
+    // tax rounding dust of the offer
+    let dust = chain.balance(router.as_str(), &native("uusd"));
+    assert!(dust <= Uint128::new(1));
+
+    // only the owner cancels before the expiry, anyone refunds an expired order
+    let expiry = Expiration::AtHeight(chain.block().height + 10);
+    place_order(&mut chain, 200_000, Expiration::Never {}).unwrap();
+    place_order(&mut chain, 200_000, expiry).unwrap();
+    assert_eq!(vec![2, 3], orders(&chain));
+    let page: OrdersResponse = chain
+        .query(QueryMsg::Orders {
+            owner: None,
+            start_after: Some(2),
+            limit: Some(1),
+        })
+        .unwrap();
+    assert_eq!(3, page.orders[0].id);
+
+    assert!(chain
+        .execute(
+            "keeper",
+            router.as_str(),
+            &ExecuteMsg::CancelOrder { id: 3 },
+            &[]
+        )
+        .is_err());
+    chain.advance_blocks(10);
+    assert!(chain
+        .execute(
+            "keeper",
+            router.as_str(),
+            &ExecuteMsg::ExecuteOrder { id: 3 },
+            &[]
+        )
+        .is_err());
+    let user_ust = chain.balance(USER, &native("uusd"));
+    chain
+        .execute(
+            "keeper",
+            router.as_str(),
+            &ExecuteMsg::CancelOrder { id: 3 },
+            &[],
+        )
+        .unwrap();
+    chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::CancelOrder { id: 2 },
+            &[],
+        )
+        .unwrap();
+    assert!(orders(&chain).is_empty());
+    assert!(chain.balance(router.as_str(), &native("uusd")) <= dust + Uint128::new(2));
+    // the refunds pay the transfer tax
+    assert_eq!(
+        user_ust + Uint128::new(2 * 999_000),
+        chain.balance(USER, &native("uusd"))
+    );
+}
+
+#[test]
+fn dca_runs_every_interval_until_the_budget_is_spent() {
+    let mut chain = setup();
+    let router = chain.router();
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::UpdateConfig {
+                owner: None,
+                permissionless: None,
+                executor_access: None,
+                market_denoms: None,
+                order_bounty: None,
+                dca_bounty: Some(Decimal::percent(1)),
+                arbitrage_bounty: None,
+                flash_loan_fee: None,
+            },
+            &[],
+        )
+        .unwrap();
+    let create_dca = ExecuteMsg::CreateDca {
+        steps: vec![pool_step(
+            native("uusd"),
+            mirror(),
+            r#"{"factory_addr":"factory"}"#,
+        )],
+        budget: asset(native("uusd"), 2_500_000),
+        amount_per_run: Uint128::new(1_000_000),
+        interval: 10,
+        tolerance: Decimal::percent(1),
+    };
+    let attributes = chain
+        .execute(
+            USER,
+            router.as_str(),
+            &create_dca,
+            &[Coin::new(2_500_000, "uusd")],
+        )
+        .unwrap();
+    assert_eq!(vec!["1"], attribute(&attributes, "dca_id"));
+    let execute_dca = |chain: &mut MockChain| {
+        chain.execute(
+            "keeper",
+            router.as_str(),
+            &ExecuteMsg::ExecuteDca { id: 1 },
+            &[],
+        )
+    };
+
+    // the budget is not part of the inventory
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::Sweep {
+                assets: vec![native("uusd")],
+                recipient: None,
+            },
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        Uint128::new(2_500_000),
+        chain.balance(router.as_str(), &native("uusd"))
+    );
+
+    let refund_before = chain.balance(USER, &native("uusd"));
+    let mut received = vec![];
+    let mut bounties = vec![];
+    for _ in 0..2 {
+        let before = chain.balance(USER, &mirror());
+        let keeper_before = chain.balance("keeper", &mirror());
+        execute_dca(&mut chain).unwrap();
+        let output = chain.balance(USER, &mirror()) - before;
+        let bounty = chain.balance("keeper", &mirror()) - keeper_before;
+        assert!(!bounty.is_zero());
+        assert_eq!(bounty, (output + bounty) * Decimal::percent(1));
+        received.push(Some(output));
+        bounties.push(bounty);
+        // the next run waits for the interval
+        assert!(execute_dca(&mut chain).is_err());
+        chain.advance_blocks(10);
+    }
+
+    // the budget left can not pay for another run, it was refunded with the last run
+    assert!(execute_dca(&mut chain).is_err());
+    assert!(chain.query::<DcaResponse>(QueryMsg::Dca { id: 1 }).is_err());
+    assert_eq!(
+        Uint128::new(499_500),
+        chain.balance(USER, &native("uusd")) - refund_before
+    );
+    let runs: DcaRunsResponse = chain
+        .query(QueryMsg::DcaRuns {
+            id: 1,
+            start_after: None,
+            limit: None,
+        })
+        .unwrap();
+    assert_eq!(
+        vec![Uint128::new(1_000_000), Uint128::new(1_000_000)],
+        runs.runs
+            .iter()
+            .map(|run| run.offer_amount)
+            .collect::<Vec<Uint128>>()
+    );
+    assert_eq!(
+        received,
+        runs.runs
+            .iter()
+            .map(|run| run.return_amount)
+            .collect::<Vec<Option<Uint128>>>()
+    );
+    // the minimum applies to the run output, bounty included
+    assert!(runs
+        .runs
+        .iter()
+        .zip(bounties)
+        .all(|(run, bounty)| run.minimum_receive < run.return_amount.unwrap() + bounty));
+
+    // only the owner cancels, the remaining budget is refunded
+    chain
+        .execute(
+            USER,
+            router.as_str(),
+            &create_dca,
+            &[Coin::new(2_500_000, "uusd")],
+        )
+        .unwrap();
+    assert!(chain
+        .execute(
+            "keeper",
+            router.as_str(),
+            &ExecuteMsg::CancelDca { id: 2 },
+            &[]
+        )
+        .is_err());
+    let before = chain.balance(USER, &native("uusd"));
+    chain
+        .execute(USER, router.as_str(), &ExecuteMsg::CancelDca { id: 2 }, &[])
+        .unwrap();
+    assert!(chain.balance(USER, &native("uusd")) > before + Uint128::new(2_490_000));
+    assert!(chain.query::<DcaResponse>(QueryMsg::Dca { id: 2 }).is_err());
+}
+
+#[test]
+fn conditional_orders_fire_on_their_trigger() {
+    let mut chain = setup();
+    let router = chain.router();
+    let factory = r#"{"factory_addr":"factory"}"#;
+    chain.set_balance(USER, &native("uluna"), Uint128::new(1_000_000));
+
+    // stop loss selling luna when the oracle prices it at 95 uusd or less
+    chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::PlaceConditionalOrder {
+                steps: vec![pool_step(native("uluna"), native("uusd"), factory)],
+                offer: asset(native("uluna"), 1_000_000),
+                trigger: OrderTrigger::ExchangeRate {
+                    base_denom: "uluna".to_string(),
+                    quote_denom: "uusd".to_string(),
+                    condition: TriggerCondition::Below,
+                    rate: Decimal::from_ratio(95u128, 1u128),
+                },
+                min_output: Uint128::new(80_000_000),
+                expiry: Expiration::Never {},
+            },
+            &[Coin::new(1_000_000, "uluna")],
+        )
+        .unwrap();
+    // take profit selling mirror once the route returns at least 1.05 uusd per mirror
+    chain
+        .execute(
+            USER,
+            MIRROR,
+            &Cw20ExecuteMsg::Send {
+                contract: router.to_string(),
+                amount: Uint128::new(100_000),
+                msg: to_binary(&Cw20HookMsg::PlaceConditionalOrder {
+                    steps: vec![pool_step(mirror(), native("uusd"), factory)],
+                    trigger: OrderTrigger::RouteOutput {
+                        condition: TriggerCondition::Above,
+                        amount: Uint128::new(1_050_000),
+                    },
+                    min_output: Uint128::new(1_000_000),
+                    expiry: Expiration::Never {},
+                })
+                .unwrap(),
+            },
+            &[],
+        )
+        .unwrap();
+
+    let trigger = |chain: &mut MockChain, id: u64| {
+        chain.execute(
+            "keeper",
+            router.as_str(),
+            &ExecuteMsg::TriggerOrder { id },
+            &[],
+        )
+    };
+    assert!(trigger(&mut chain, 1).is_err());
+    assert!(trigger(&mut chain, 2).is_err());
+    // conditional orders are only filled through their trigger
+    chain
+        .querier()
+        .with_exchange_rates(&[("uusd", Decimal::from_ratio(90u128, 1u128))]);
+    assert!(chain
+        .execute(
+            "keeper",
+            router.as_str(),
+            &ExecuteMsg::ExecuteOrder { id: 1 },
+            &[]
+        )
+        .is_err());
+
+    let before = chain.balance(USER, &native("uusd"));
+    let attributes = trigger(&mut chain, 1).unwrap();
+    assert_eq!(vec!["90"], attribute(&attributes, "trigger_value"));
+    let output: Uint128 = attribute(&attributes, "output")[0].parse().unwrap();
+    assert!(output >= Uint128::new(80_000_000));
+    // the owner pays the transfer tax on the output
+    let received = chain.balance(USER, &native("uusd")) - before;
+    assert!(received < output && output - received <= output * Decimal::permille(1));
+
+    chain.set_balance("mirror-ust", &native("uusd"), Uint128::new(110_000_000_000));
+    let attributes = trigger(&mut chain, 2).unwrap();
+    let value: Uint128 = attribute(&attributes, "trigger_value")[0].parse().unwrap();
+    assert!(value >= Uint128::new(1_050_000));
+    assert_eq!(Uint128::zero(), chain.balance(router.as_str(), &mirror()));
+    assert!(chain
+        .query::<OrdersResponse>(QueryMsg::Orders {
+            owner: None,
+            start_after: None,
+            limit: None,
+        })
+        .unwrap()
+        .orders
+        .is_empty());
+}
+
+#[test]
+fn anyone_triggers_registered_arbitrages_for_a_bounty() {
+    let mut chain = setup();
+    let router = chain.router();
+    chain.set_balance(OWNER, &native("uusd"), Uint128::new(2_000_000_000));
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::Deposit {},
+            &[Coin::new(1_000_000_000, "uusd")],
+        )
+        .unwrap();
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::UpdateConfig {
+                owner: None,
+                permissionless: None,
+                executor_access: Some(ExecutorAccess::Restricted),
+                market_denoms: None,
+                order_bounty: None,
+                dca_bounty: None,
+                arbitrage_bounty: Some(Decimal::percent(10)),
+                flash_loan_fee: None,
+            },
+            &[],
+        )
+        .unwrap();
+
+    // luna is cheaper on Terraswap than on the market
+    let steps = vec![
+        pool_step(
+            native("uusd"),
+            native("uluna"),
+            r#"{"factory_addr":"factory"}"#,
+        ),
+        market_step(native("uluna"), native("uusd")),
+    ];
+    let register = |minimum_profit: u128| ExecuteMsg::RegisterArbitrage {
+        steps: steps.clone(),
+        minimum_profit: Uint128::new(minimum_profit),
+    };
+    assert!(chain
+        .execute(USER, router.as_str(), &register(1_000_000), &[])
+        .is_err());
+    let not_circular = ExecuteMsg::RegisterArbitrage {
+        steps: steps[..1].to_vec(),
+        minimum_profit: Uint128::new(1_000_000),
+    };
+    assert!(chain
+        .execute(OWNER, router.as_str(), &not_circular, &[])
+        .is_err());
+    chain
+        .execute(OWNER, router.as_str(), &register(1_000_000), &[])
+        .unwrap();
+    chain
+        .execute(OWNER, router.as_str(), &register(100_000_000), &[])
+        .unwrap();
+    let arbitrages: ArbitragesResponse = chain
+        .query(QueryMsg::Arbitrages {
+            start_after: None,
+            limit: None,
+        })
+        .unwrap();
+    assert_eq!(2, arbitrages.arbitrages.len());
+
+    let trigger = |id: u64, amount: u128| ExecuteMsg::TriggerArbitrage {
+        id,
+        amount: Uint128::new(amount),
+    };
+    assert!(chain
+        .execute("keeper", router.as_str(), &trigger(1, 1_000_000_001), &[])
+        .is_err());
+
+    // the keeper is not an executor but is paid a share of the profit
+    let inventory = chain.balance(router.as_str(), &native("uusd"));
+    let attributes = chain
+        .execute("keeper", router.as_str(), &trigger(1, 100_000_000), &[])
+        .unwrap();
+    let profit = Uint128::new(attribute(&attributes, "profit")[0].parse().unwrap());
+    let bounty = Uint128::new(attribute(&attributes, "bounty")[0].parse().unwrap());
+    assert!(profit >= Uint128::new(1_000_000));
+    assert!(!bounty.is_zero());
+    // the tax on the bounty leaves at most one unit of rounding dust
+    let grown = chain.balance(router.as_str(), &native("uusd")) - inventory;
+    assert!(grown >= profit && grown - profit <= Uint128::new(1));
+    // the keeper pays the transfer tax on the bounty
+    let received = chain.balance("keeper", &native("uusd"));
+    assert!(
+        received < bounty && bounty - received <= bounty * Decimal::permille(1) + Uint128::new(1)
+    );
+
+    // the loop is no longer profitable enough once the pool moved
+    let inventory = chain.balance(router.as_str(), &native("uusd"));
+    assert!(chain
+        .execute("keeper", router.as_str(), &trigger(2, 900_000_000), &[])
+        .is_err());
+    assert_eq!(inventory, chain.balance(router.as_str(), &native("uusd")));
+
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::Pause { operations: None },
+            &[],
+        )
+        .unwrap();
+    assert!(chain
+        .execute("keeper", router.as_str(), &trigger(1, 100_000_000), &[])
+        .is_err());
+
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::DeleteArbitrage { id: 2 },
+            &[],
+        )
+        .unwrap();
+    let arbitrages: ArbitragesResponse = chain
+        .query(QueryMsg::Arbitrages {
+            start_after: None,
+            limit: None,
+        })
+        .unwrap();
+    assert_eq!(
+        vec![1],
+        arbitrages
+            .arbitrages
+            .iter()
+            .map(|arbitrage| arbitrage.id)
+            .collect::<Vec<u64>>()
+    );
+}
+
+#[test]
+fn flash_loan_strategy_repays_the_lender() {
+    let mut chain = setup();
+    let router = chain.router();
+    chain
+        .querier()
+        .with_flash_lender(LENDER, Decimal::permille(3));
+    chain.set_balance(LENDER, &native("uusd"), Uint128::new(10_000_000_000));
+
+    // luna is cheaper on Terraswap than on the market
+    let flash_loan = |amount: u128, minimum_profit: u128| ExecuteMsg::ExecuteFlashLoanStrategy {
+        steps: vec![
+            pool_step(
+                native("uusd"),
+                native("uluna"),
+                r#"{"factory_addr":"factory"}"#,
+            ),
+            market_step(native("uluna"), native("uusd")),
+        ],
+        amount: Uint128::new(amount),
+        minimum_profit: Uint128::new(minimum_profit),
+    };
+    let err = chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &flash_loan(100_000_000, 1_000_000),
+            &[],
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("no flash lender configured"));
+
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::UpdateFlashLender {
+                flash_lender: Some(LENDER.to_string()),
+            },
+            &[],
+        )
+        .unwrap();
+    assert!(chain
+        .execute(
+            USER,
+            router.as_str(),
+            &flash_loan(100_000_000, 1_000_000),
+            &[]
+        )
+        .is_err());
+    assert!(chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::FlashLoanCallback {},
+            &[]
+        )
+        .is_err());
+    // the lender can not call back through a cw20 transfer
+    let factory = r#"{"factory_addr":"factory"}"#;
+    let err = chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::ExecuteFlashLoanStrategy {
+                steps: vec![
+                    pool_step(mirror(), native("uusd"), factory),
+                    market_step(native("uusd"), native("uluna")),
+                    pool_step(native("uluna"), mirror(), factory),
+                ],
+                amount: Uint128::new(100_000_000),
+                minimum_profit: Uint128::zero(),
+            },
+            &[],
+        )
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("flash loans can only borrow native tokens"));
+
+    // the router holds no inventory, the whole offer is borrowed
+    let attributes = chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &flash_loan(100_000_000, 1_000_000),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(vec!["300000"], attribute(&attributes, "fee"));
+    assert_eq!(vec!["100300000uusd"], attribute(&attributes, "repaid"));
+    // the lender paid the transfer tax on the loan and was repaid with its fee
+    assert_eq!(
+        Uint128::new(10_000_000_000 - 100_000 + 300_000),
+        chain.balance(LENDER, &native("uusd"))
+    );
+    let profit = chain.balance(router.as_str(), &native("uusd"));
+    assert!(profit >= Uint128::new(1_000_000));
+    assert_eq!(
+        Uint128::zero(),
+        chain.balance(router.as_str(), &native("uluna"))
+    );
+
+    // an unprofitable loop reverts the loan
+    let lender_balance = chain.balance(LENDER, &native("uusd"));
+    assert!(chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &flash_loan(900_000_000, 100_000_000),
+            &[]
+        )
+        .is_err());
+    assert_eq!(lender_balance, chain.balance(LENDER, &native("uusd")));
+    assert_eq!(profit, chain.balance(router.as_str(), &native("uusd")));
+
+    // clearing the lender disables flash loan strategies
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::UpdateFlashLender { flash_lender: None },
+            &[],
+        )
+        .unwrap();
+    let err = chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &flash_loan(100_000_000, 1_000_000),
+            &[],
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("no flash lender configured"));
+}
+
+#[test]
+fn router_lends_its_inventory_for_a_fee() {
+    let mut chain = setup();
+    let router = chain.router();
+    chain.with_flash_borrower(BORROWER);
+    chain.set_balance(BORROWER, &native("uusd"), Uint128::new(10_000_000));
+    chain.set_balance(BORROWER, &mirror(), Uint128::new(10_000_000));
+    chain.set_balance(OWNER, &native("uusd"), Uint128::new(2_000_000_000));
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::Deposit {},
+            &[Coin::new(1_000_000_000, "uusd")],
+        )
+        .unwrap();
+    chain.set_balance(router.as_str(), &mirror(), Uint128::new(1_000_000_000));
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::UpdateConfig {
+                owner: None,
+                permissionless: None,
+                executor_access: None,
+                market_denoms: None,
+                order_bounty: None,
+                dca_bounty: None,
+                arbitrage_bounty: None,
+                flash_loan_fee: Some(Decimal::permille(5)),
+            },
+            &[],
+        )
+        .unwrap();
+
+    let flash_loan = |info: AssetInfo, amount: u128, repay: u128| ExecuteMsg::FlashLoan {
+        asset: info.clone(),
+        amount: Uint128::new(amount),
+        callback: to_binary(&MockBorrowerMsg::Repay {
+            asset: asset(info, repay),
+        })
+        .unwrap(),
+    };
+    assert!(chain
+        .execute(
+            BORROWER,
+            router.as_str(),
+            &flash_loan(native("uusd"), 1_000_000_001, 1_010_000_000),
+            &[]
+        )
+        .is_err());
+
+    // the borrower also covers the 100_000 tax the router paid on the loan
+    let err = chain
+        .execute(
+            BORROWER,
+            router.as_str(),
+            &flash_loan(native("uusd"), 100_000_000, 100_500_000),
+            &[],
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("flash loan not repaid"));
+    assert_eq!(
+        Uint128::new(1_000_000_000),
+        chain.balance(router.as_str(), &native("uusd"))
+    );
+
+    let attributes = chain
+        .execute(
+            BORROWER,
+            router.as_str(),
+            &flash_loan(native("uusd"), 100_000_000, 100_600_000),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(vec!["500000"], attribute(&attributes, "fee"));
+    assert_eq!(
+        Uint128::new(1_000_500_000),
+        chain.balance(router.as_str(), &native("uusd"))
+    );
+
+    // cw20 loans are sent with the callback
+    chain
+        .execute(
+            BORROWER,
+            router.as_str(),
+            &flash_loan(mirror(), 100_000_000, 100_500_000),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(
+        Uint128::new(1_000_500_000),
+        chain.balance(router.as_str(), &mirror())
+    );
+
+    assert!(chain
+        .execute(
+            USER,
+            router.as_str(),
+            &ExecuteMsg::FinalizeFlashLoan {
+                borrower: USER.to_string(),
+                asset_info: mirror(),
+                initial_inventory: Uint128::zero(),
+                fee: Uint128::zero(),
+            },
+            &[]
+        )
+        .is_err());
+
+    chain
+        .execute(
+            OWNER,
+            router.as_str(),
+            &ExecuteMsg::Pause { operations: None },
+            &[],
+        )
+        .unwrap();
+    let err = chain
+        .execute(
+            BORROWER,
+            router.as_str(),
+            &flash_loan(mirror(), 100_000_000, 100_500_000),
+            &[],
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("router is paused"));
+}
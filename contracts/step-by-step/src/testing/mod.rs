@@ -0,0 +1,10 @@
+//! Test helpers running the router without a chain, not available on wasm builds.
+
+mod chain;
+mod querier;
+
+pub use chain::{MockBorrowerMsg, MockChain};
+pub use querier::{mock_dependencies, WasmMockQuerier};
+
+#[cfg(test)]
+mod integration_tests;
@@ -0,0 +1,617 @@
+use std::collections::BTreeMap;
+
+use cosmwasm_std::testing::{MockApi, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+    from_binary, from_slice, to_binary, AllBalanceResponse, BalanceResponse, BankQuery, Binary,
+    Coin, Decimal, OwnedDeps, Querier, QuerierResult, QueryRequest, StdError, StdResult,
+    SystemError, SystemResult, Uint128, WasmQuery,
+};
+use cw20::{BalanceResponse as Cw20BalanceResponse, Cw20QueryMsg};
+use terra_cosmwasm::{
+    ExchangeRateItem, ExchangeRatesResponse, SwapResponse, TaxCapResponse, TaxRateResponse,
+    TerraQuery, TerraQueryWrapper,
+};
+use terraswap::asset::Asset as TerraswapAsset;
+use terraswap::asset::AssetInfo as TerraswapAssetInfo;
+use terraswap::asset::PairInfo as TerraswapPairInfo;
+use terraswap::factory::QueryMsg as FactoryQueryMsg;
+use terraswap::pair::QueryMsg as PairQueryMsg;
+use terraswap::pair::{PoolResponse, SimulationResponse};
+
+use crate::asset::{Asset, AssetInfo};
+use crate::lender::{FlashFeeResponse, FlashLenderQueryMsg};
+
+pub(crate) static DECIMAL_FRACTION: Uint128 = Uint128::new(1_000_000_000_000_000_000u128);
+
+/// mock_dependencies is a drop-in replacement for cosmwasm_std::testing::mock_dependencies
+/// answering the Terra, cw20 and Terraswap queries through a `WasmMockQuerier`.
+pub fn mock_dependencies(
+    contract_balance: &[Coin],
+) -> OwnedDeps<MockStorage, MockApi, WasmMockQuerier> {
+    let mut querier = WasmMockQuerier::default();
+    querier.with_balance(MOCK_CONTRACT_ADDR, contract_balance);
+
+    OwnedDeps {
+        storage: MockStorage::default(),
+        api: MockApi::default(),
+        querier,
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct MockPair {
+    pub(crate) factory: String,
+    pub(crate) contract_addr: String,
+    pub(crate) asset_infos: [AssetInfo; 2],
+    pub(crate) commission: Decimal,
+    pub(crate) liquidity_token: String,
+}
+
+impl MockPair {
+    fn info(&self) -> TerraswapPairInfo {
+        TerraswapPairInfo {
+            asset_infos: [
+                to_terraswap_info(&self.asset_infos[0]),
+                to_terraswap_info(&self.asset_infos[1]),
+            ],
+            contract_addr: self.contract_addr.clone(),
+            liquidity_token: self.liquidity_token.clone(),
+        }
+    }
+
+    pub(crate) fn liquidity_token_info(&self) -> AssetInfo {
+        AssetInfo::Token {
+            contract_addr: self.liquidity_token.clone(),
+        }
+    }
+
+    pub(crate) fn ask_info(&self, offer_info: &AssetInfo) -> StdResult<AssetInfo> {
+        if offer_info.equal(&self.asset_infos[0]) {
+            Ok(self.asset_infos[1].clone())
+        } else if offer_info.equal(&self.asset_infos[1]) {
+            Ok(self.asset_infos[0].clone())
+        } else {
+            Err(StdError::generic_err(format!(
+                "asset {} is not traded by pair {}",
+                offer_info, self.contract_addr
+            )))
+        }
+    }
+}
+
+fn to_terraswap_info(info: &AssetInfo) -> TerraswapAssetInfo {
+    match info {
+        AssetInfo::Token { contract_addr } => TerraswapAssetInfo::Token {
+            contract_addr: contract_addr.to_string(),
+        },
+        AssetInfo::NativeToken { denom } => TerraswapAssetInfo::NativeToken {
+            denom: denom.to_string(),
+        },
+    }
+}
+
+pub(crate) fn from_terraswap_info(info: &TerraswapAssetInfo) -> AssetInfo {
+    match info {
+        TerraswapAssetInfo::Token { contract_addr } => AssetInfo::Token {
+            contract_addr: contract_addr.to_string(),
+        },
+        TerraswapAssetInfo::NativeToken { denom } => AssetInfo::NativeToken {
+            denom: denom.to_string(),
+        },
+    }
+}
+
+pub(crate) fn native(denom: &str) -> AssetInfo {
+    AssetInfo::NativeToken {
+        denom: denom.to_string(),
+    }
+}
+
+/// Shares minted by a Terraswap pair for its first deposit
+pub(crate) fn initial_share(amount_a: Uint128, amount_b: Uint128) -> Uint128 {
+    Uint128::new(((amount_a.u128() as f64) * (amount_b.u128() as f64)).sqrt() as u128)
+}
+
+/// Constant product swap as done by the Terraswap pair,
+/// returns the return, spread and commission amounts.
+pub(crate) fn compute_swap(
+    offer_pool: Uint128,
+    ask_pool: Uint128,
+    offer_amount: Uint128,
+    commission_rate: Decimal,
+) -> StdResult<(Uint128, Uint128, Uint128)> {
+    let return_amount = ask_pool
+        .checked_sub(ask_pool.multiply_ratio(offer_pool, offer_pool.checked_add(offer_amount)?))?;
+    let spread_amount = offer_amount
+        .multiply_ratio(ask_pool, offer_pool)
+        .saturating_sub(return_amount);
+    let commission_amount = return_amount * commission_rate;
+
+    Ok((
+        return_amount.checked_sub(commission_amount)?,
+        spread_amount,
+        commission_amount,
+    ))
+}
+
+/// Answers the bank, cw20, Terraswap factory and pair and flash lender queries as well as the
+/// Terra market, oracle and treasury queries from balances and prices set by each test.
+#[derive(Clone, Default)]
+pub struct WasmMockQuerier {
+    /// holder -> denom -> amount
+    balances: BTreeMap<String, BTreeMap<String, Uint128>>,
+    /// token -> holder -> amount
+    tokens: BTreeMap<String, BTreeMap<String, Uint128>>,
+    pub(crate) pairs: Vec<MockPair>,
+    /// units of each denom worth one uluna
+    exchange_rates: BTreeMap<String, Decimal>,
+    pub(crate) tax_rate: Decimal,
+    pub(crate) tax_caps: BTreeMap<String, Uint128>,
+    /// (token, owner, spender) -> amount
+    allowances: BTreeMap<(String, String, String), Uint128>,
+    /// flash lender -> fee rate
+    lenders: BTreeMap<String, Decimal>,
+}
+
+impl WasmMockQuerier {
+    pub fn with_balance(&mut self, address: &str, balance: &[Coin]) {
+        for coin in balance {
+            self.set_balance(&native(&coin.denom), address, coin.amount);
+        }
+    }
+
+    pub fn with_token_balances(&mut self, token: &str, balances: &[(&str, Uint128)]) {
+        let info = AssetInfo::Token {
+            contract_addr: token.to_string(),
+        };
+        for (holder, amount) in balances {
+            self.set_balance(&info, holder, *amount);
+        }
+    }
+
+    /// Tax charged on native transfers, `uluna` is never taxed
+    pub fn with_tax(&mut self, rate: Decimal, caps: &[Coin]) {
+        self.tax_rate = rate;
+        self.tax_caps = caps
+            .iter()
+            .map(|cap| (cap.denom.clone(), cap.amount))
+            .collect();
+    }
+
+    /// Units of each denom the market and the oracle give for one uluna
+    pub fn with_exchange_rates(&mut self, rates: &[(&str, Decimal)]) {
+        for (denom, rate) in rates {
+            self.exchange_rates.insert(denom.to_string(), *rate);
+        }
+    }
+
+    /// Registers a Terraswap pair created by `factory`, holding `pool` as its liquidity.
+    /// Its liquidity token is `<contract_addr>-lp`, the initial shares are held by the factory.
+    pub fn with_pair(
+        &mut self,
+        factory: &str,
+        contract_addr: &str,
+        pool: [Asset; 2],
+        commission: Decimal,
+    ) {
+        for asset in pool.iter() {
+            self.set_balance(&asset.info, contract_addr, asset.amount);
+        }
+        let pair = MockPair {
+            factory: factory.to_string(),
+            contract_addr: contract_addr.to_string(),
+            asset_infos: [pool[0].info.clone(), pool[1].info.clone()],
+            commission,
+            liquidity_token: format!("{}-lp", contract_addr),
+        };
+        self.set_balance(
+            &pair.liquidity_token_info(),
+            factory,
+            initial_share(pool[0].amount, pool[1].amount),
+        );
+        self.pairs.push(pair);
+    }
+
+    /// Registers a flash lender charging `fee_rate` of the principal, it lends its own balances
+    pub fn with_flash_lender(&mut self, contract_addr: &str, fee_rate: Decimal) {
+        self.lenders.insert(contract_addr.to_string(), fee_rate);
+    }
+
+    pub(crate) fn flash_fee_rate(&self, lender: &str) -> Option<Decimal> {
+        self.lenders.get(lender).copied()
+    }
+
+    pub fn balance(&self, info: &AssetInfo, holder: &str) -> Uint128 {
+        let amount = match info {
+            AssetInfo::NativeToken { denom } => self
+                .balances
+                .get(holder)
+                .and_then(|balances| balances.get(denom)),
+            AssetInfo::Token { contract_addr } => self
+                .tokens
+                .get(contract_addr)
+                .and_then(|balances| balances.get(holder)),
+        };
+        amount.copied().unwrap_or_default()
+    }
+
+    pub(crate) fn set_balance(&mut self, info: &AssetInfo, holder: &str, amount: Uint128) {
+        match info {
+            AssetInfo::NativeToken { denom } => self
+                .balances
+                .entry(holder.to_string())
+                .or_default()
+                .insert(denom.to_string(), amount),
+            AssetInfo::Token { contract_addr } => self
+                .tokens
+                .entry(contract_addr.to_string())
+                .or_default()
+                .insert(holder.to_string(), amount),
+        };
+    }
+
+    pub(crate) fn total_supply(&self, token: &str) -> Uint128 {
+        self.tokens
+            .get(token)
+            .map(|balances| balances.values().copied().sum())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn increase_allowance(
+        &mut self,
+        token: &str,
+        owner: &str,
+        spender: &str,
+        amount: Uint128,
+    ) {
+        *self
+            .allowances
+            .entry((token.to_string(), owner.to_string(), spender.to_string()))
+            .or_default() += amount;
+    }
+
+    /// Transfers `amount` of `token` from `owner` to `spender` using the allowance it was given
+    pub(crate) fn transfer_from(
+        &mut self,
+        token: &str,
+        owner: &str,
+        spender: &str,
+        amount: Uint128,
+    ) -> StdResult<()> {
+        let key = (token.to_string(), owner.to_string(), spender.to_string());
+        let allowance = self.allowances.get(&key).copied().unwrap_or_default();
+        let allowance = allowance.checked_sub(amount).map_err(|_| {
+            StdError::generic_err(format!(
+                "insufficient allowance; {} allowed {} {}, needs {}",
+                owner, spender, allowance, amount
+            ))
+        })?;
+        self.allowances.insert(key, allowance);
+
+        let info = AssetInfo::Token {
+            contract_addr: token.to_string(),
+        };
+        self.transfer(&info, owner, spender, amount)
+    }
+
+    pub(crate) fn add(&mut self, info: &AssetInfo, holder: &str, amount: Uint128) -> StdResult<()> {
+        let balance = self.balance(info, holder).checked_add(amount)?;
+        self.set_balance(info, holder, balance);
+        Ok(())
+    }
+
+    pub(crate) fn sub(&mut self, info: &AssetInfo, holder: &str, amount: Uint128) -> StdResult<()> {
+        let balance = self.balance(info, holder);
+        let balance = balance.checked_sub(amount).map_err(|_| {
+            StdError::generic_err(format!(
+                "insufficient funds; {} has {}{}, needs {}",
+                holder, balance, info, amount
+            ))
+        })?;
+        self.set_balance(info, holder, balance);
+        Ok(())
+    }
+
+    pub(crate) fn transfer(
+        &mut self,
+        info: &AssetInfo,
+        from: &str,
+        to: &str,
+        amount: Uint128,
+    ) -> StdResult<()> {
+        self.sub(info, from, amount)?;
+        self.add(info, to, amount)
+    }
+
+    /// Tax charged to the sender on top of a native transfer of `amount`
+    pub(crate) fn tax(&self, denom: &str, amount: Uint128) -> Uint128 {
+        if denom == "uluna" {
+            return Uint128::zero();
+        }
+
+        let tax = amount * self.tax_rate;
+        match self.tax_caps.get(denom) {
+            Some(cap) => std::cmp::min(tax, *cap),
+            None => tax,
+        }
+    }
+
+    pub(crate) fn send_native(&mut self, from: &str, to: &str, coin: &Coin) -> StdResult<()> {
+        let info = native(&coin.denom);
+        self.sub(
+            &info,
+            from,
+            coin.amount + self.tax(&coin.denom, coin.amount),
+        )?;
+        self.add(&info, to, coin.amount)
+    }
+
+    fn exchange_rate(&self, denom: &str) -> StdResult<Decimal> {
+        if denom == "uluna" {
+            return Ok(Decimal::one());
+        }
+
+        self.exchange_rates
+            .get(denom)
+            .copied()
+            .ok_or_else(|| StdError::generic_err(format!("no exchange rate for {}", denom)))
+    }
+
+    pub(crate) fn market_swap(&self, offer_coin: &Coin, ask_denom: &str) -> StdResult<Uint128> {
+        let offer_rate = self.exchange_rate(&offer_coin.denom)?;
+        let ask_rate = self.exchange_rate(ask_denom)?;
+        let luna_amount = offer_coin
+            .amount
+            .multiply_ratio(DECIMAL_FRACTION, DECIMAL_FRACTION * offer_rate);
+        Ok(luna_amount * ask_rate)
+    }
+
+    pub(crate) fn pair(&self, contract_addr: &str) -> Option<MockPair> {
+        self.pairs
+            .iter()
+            .find(|pair| pair.contract_addr == contract_addr)
+            .cloned()
+    }
+
+    fn handle_query(&self, request: QueryRequest<TerraQueryWrapper>) -> StdResult<Binary> {
+        match request {
+            QueryRequest::Bank(BankQuery::Balance { address, denom }) => {
+                to_binary(&BalanceResponse {
+                    amount: Coin {
+                        amount: self.balance(&native(&denom), &address),
+                        denom,
+                    },
+                })
+            }
+            QueryRequest::Bank(BankQuery::AllBalances { address }) => {
+                to_binary(&AllBalanceResponse {
+                    amount: self
+                        .balances
+                        .get(&address)
+                        .map(|balances| {
+                            balances
+                                .iter()
+                                .filter(|(_, amount)| !amount.is_zero())
+                                .map(|(denom, amount)| Coin::new(amount.u128(), denom))
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                })
+            }
+            QueryRequest::Wasm(WasmQuery::Smart { contract_addr, msg }) => {
+                self.query_contract(&contract_addr, &msg)
+            }
+            QueryRequest::Custom(TerraQueryWrapper { query_data, .. }) => match query_data {
+                TerraQuery::TaxRate {} => to_binary(&TaxRateResponse {
+                    rate: self.tax_rate,
+                }),
+                TerraQuery::TaxCap { denom } => to_binary(&TaxCapResponse {
+                    cap: self.tax_caps.get(&denom).copied().unwrap_or(Uint128::MAX),
+                }),
+                TerraQuery::Swap {
+                    offer_coin,
+                    ask_denom,
+                } => to_binary(&SwapResponse {
+                    receive: Coin {
+                        amount: self.market_swap(&offer_coin, &ask_denom)?,
+                        denom: ask_denom,
+                    },
+                }),
+                TerraQuery::ExchangeRates {
+                    base_denom,
+                    quote_denoms,
+                } => {
+                    let base_rate = self.exchange_rate(&base_denom)?;
+                    let exchange_rates = quote_denoms
+                        .into_iter()
+                        .map(|quote_denom| {
+                            Ok(ExchangeRateItem {
+                                exchange_rate: Decimal::from_ratio(
+                                    DECIMAL_FRACTION * self.exchange_rate(&quote_denom)?,
+                                    DECIMAL_FRACTION * base_rate,
+                                ),
+                                quote_denom,
+                            })
+                        })
+                        .collect::<StdResult<Vec<ExchangeRateItem>>>()?;
+                    to_binary(&ExchangeRatesResponse {
+                        base_denom,
+                        exchange_rates,
+                    })
+                }
+                _ => Err(StdError::generic_err("unsupported terra query")),
+            },
+            _ => Err(StdError::generic_err("unsupported query")),
+        }
+    }
+
+    fn query_contract(&self, contract_addr: &str, msg: &Binary) -> StdResult<Binary> {
+        if let Some(pair) = self.pair(contract_addr) {
+            return match from_binary(msg)? {
+                PairQueryMsg::Pair {} => to_binary(&pair.info()),
+                PairQueryMsg::Pool {} => to_binary(&PoolResponse {
+                    assets: [
+                        TerraswapAsset {
+                            info: to_terraswap_info(&pair.asset_infos[0]),
+                            amount: self.balance(&pair.asset_infos[0], contract_addr),
+                        },
+                        TerraswapAsset {
+                            info: to_terraswap_info(&pair.asset_infos[1]),
+                            amount: self.balance(&pair.asset_infos[1], contract_addr),
+                        },
+                    ],
+                    total_share: self.total_supply(&pair.liquidity_token),
+                }),
+                PairQueryMsg::Simulation { offer_asset } => {
+                    let offer_info = from_terraswap_info(&offer_asset.info);
+                    let ask_info = pair.ask_info(&offer_info)?;
+                    let (return_amount, spread_amount, commission_amount) = compute_swap(
+                        self.balance(&offer_info, contract_addr),
+                        self.balance(&ask_info, contract_addr),
+                        offer_asset.amount,
+                        pair.commission,
+                    )?;
+                    to_binary(&SimulationResponse {
+                        return_amount,
+                        spread_amount,
+                        commission_amount,
+                    })
+                }
+                _ => Err(StdError::generic_err("unsupported pair query")),
+            };
+        }
+
+        if let Some(fee_rate) = self.flash_fee_rate(contract_addr) {
+            return match from_binary(msg)? {
+                FlashLenderQueryMsg::FlashFee { asset } => to_binary(&FlashFeeResponse {
+                    fee: asset.amount * fee_rate,
+                }),
+            };
+        }
+
+        if self.pairs.iter().any(|pair| pair.factory == contract_addr) {
+            return match from_binary(msg)? {
+                FactoryQueryMsg::Pair { asset_infos } => {
+                    let asset_infos = [
+                        from_terraswap_info(&asset_infos[0]),
+                        from_terraswap_info(&asset_infos[1]),
+                    ];
+                    let pair = self
+                        .pairs
+                        .iter()
+                        .find(|pair| {
+                            pair.factory == contract_addr
+                                && pair
+                                    .ask_info(&asset_infos[0])
+                                    .map(|ask_info| ask_info.equal(&asset_infos[1]))
+                                    .unwrap_or(false)
+                        })
+                        .ok_or_else(|| StdError::not_found("pair info"))?;
+                    to_binary(&pair.info())
+                }
+                _ => Err(StdError::generic_err("unsupported factory query")),
+            };
+        }
+
+        match from_binary(msg)? {
+            Cw20QueryMsg::Balance { address } => to_binary(&Cw20BalanceResponse {
+                balance: self.balance(
+                    &AssetInfo::Token {
+                        contract_addr: contract_addr.to_string(),
+                    },
+                    &address,
+                ),
+            }),
+            _ => Err(StdError::generic_err("unsupported token query")),
+        }
+    }
+}
+
+impl Querier for WasmMockQuerier {
+    fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
+        let request: QueryRequest<TerraQueryWrapper> = match from_slice(bin_request) {
+            Ok(request) => request,
+            Err(e) => {
+                return SystemResult::Err(SystemError::InvalidRequest {
+                    error: format!("Parsing query request: {}", e),
+                    request: bin_request.into(),
+                })
+            }
+        };
+        SystemResult::Ok(self.handle_query(request).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::querier::{query_balance, query_token_balance};
+    use cosmwasm_std::{Addr, QuerierWrapper};
+    use terra_cosmwasm::TerraQuerier;
+
+    #[test]
+    fn answers_configured_queries() {
+        let mut deps = mock_dependencies(&[Coin::new(100, "uusd")]);
+        deps.querier
+            .with_token_balances("mirror", &[(MOCK_CONTRACT_ADDR, Uint128::new(50))]);
+        deps.querier
+            .with_exchange_rates(&[("uusd", Decimal::percent(5000))]);
+        deps.querier.with_pair(
+            "factory",
+            "pair",
+            [
+                Asset {
+                    info: native("uluna"),
+                    amount: Uint128::new(1_000),
+                },
+                Asset {
+                    info: native("uusd"),
+                    amount: Uint128::new(50_000),
+                },
+            ],
+            Decimal::zero(),
+        );
+        let querier = QuerierWrapper::new(&deps.querier);
+        let contract = Addr::unchecked(MOCK_CONTRACT_ADDR);
+
+        assert_eq!(
+            Uint128::new(100),
+            query_balance(&querier, contract.clone(), native("uusd")).unwrap()
+        );
+        assert_eq!(
+            Uint128::new(50),
+            query_token_balance(&querier, Addr::unchecked("mirror"), contract).unwrap()
+        );
+
+        let pair: TerraswapPairInfo = querier
+            .query(&QueryRequest::Wasm(WasmQuery::Smart {
+                contract_addr: "factory".to_string(),
+                msg: to_binary(&FactoryQueryMsg::Pair {
+                    asset_infos: [
+                        to_terraswap_info(&native("uusd")),
+                        to_terraswap_info(&native("uluna")),
+                    ],
+                })
+                .unwrap(),
+            }))
+            .unwrap();
+        assert_eq!("pair", pair.contract_addr);
+
+        let terra = TerraQuerier::new(&querier);
+        assert_eq!(
+            Coin::new(500, "uusd"),
+            terra
+                .query_swap(Coin::new(10, "uluna"), "uusd")
+                .unwrap()
+                .receive
+        );
+        assert_eq!(
+            Decimal::percent(2),
+            terra
+                .query_exchange_rates("uusd", vec!["uluna"])
+                .unwrap()
+                .exchange_rates[0]
+                .exchange_rate
+        );
+    }
+}